@@ -0,0 +1,97 @@
+pub trait Clock: Send + Sync {
+    fn now(&self) -> std::time::Instant;
+    fn sleep(&self, duration: std::time::Duration);
+    /// Calendar time, for anything that follows the time of day.
+    fn wall_time(&self) -> chrono::DateTime<chrono::Utc>;
+}
+
+#[derive(Default)]
+pub struct SystemClock {}
+
+impl Clock for SystemClock {
+    fn now(&self) -> std::time::Instant {
+        std::time::Instant::now()
+    }
+
+    fn sleep(&self, duration: std::time::Duration) {
+        std::thread::sleep(duration);
+    }
+
+    fn wall_time(&self) -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc::now()
+    }
+}
+
+/// Virtual time that only moves when advanced; sleeping advances it instantly.
+pub struct ManualClock {
+    start: std::time::Instant,
+    start_wall_time: chrono::DateTime<chrono::Utc>,
+    elapsed: std::sync::Mutex<std::time::Duration>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::starting_at(chrono::Utc::now())
+    }
+}
+
+impl ManualClock {
+    pub fn starting_at(start_wall_time: chrono::DateTime<chrono::Utc>) -> Self {
+        ManualClock {
+            start: std::time::Instant::now(),
+            start_wall_time,
+            elapsed: std::sync::Mutex::new(std::time::Duration::ZERO),
+        }
+    }
+
+    pub fn advance(&self, duration: std::time::Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    pub fn elapsed(&self) -> std::time::Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> std::time::Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: std::time::Duration) {
+        self.advance(duration);
+    }
+
+    fn wall_time(&self) -> chrono::DateTime<chrono::Utc> {
+        self.start_wall_time + self.elapsed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_only_moves_when_advanced() {
+        let clock = ManualClock::default();
+        let start = clock.now();
+
+        clock.advance(std::time::Duration::from_secs(5));
+        clock.sleep(std::time::Duration::from_secs(10));
+
+        assert!(clock.now() - start == std::time::Duration::from_secs(15));
+        assert!(clock.elapsed() == std::time::Duration::from_secs(15));
+    }
+
+    #[test]
+    fn manual_wall_time_follows_elapsed() {
+        let start = chrono::DateTime::parse_from_rfc3339("2024-01-01T08:00:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        let clock = ManualClock::starting_at(start);
+
+        clock.advance(std::time::Duration::from_secs(90));
+
+        assert!(clock.wall_time() == start + chrono::Duration::seconds(90));
+    }
+}
@@ -0,0 +1,305 @@
+use super::watcher::ReloadSubscriber;
+use super::{Config, ConfigError, ReadConfig};
+use crate::clock::{Clock, SystemClock};
+use crate::temperature_controller::events::ControllerEvent;
+
+pub const DEFAULT_TTL: std::time::Duration = std::time::Duration::from_secs(60);
+/// Failed refreshes are retried after the TTL, doubling with every further
+/// failure up to this interval.
+pub const MAX_RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15 * 60);
+
+/// What the cache is serving. `stale` is set when the last refresh failed and
+/// cleared by the next one that succeeds.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CacheStatus {
+    pub age: Option<std::time::Duration>,
+    pub stale: bool,
+    pub refreshing: bool,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entry: Option<(Config, std::time::Instant)>,
+    stale: bool,
+    refreshing: bool,
+    /// When the last refresh failed and how many have failed in a row.
+    failed_refreshes: Option<(std::time::Instant, u32)>,
+}
+
+impl CacheState {
+    /// Whether a refresh should start at `now`, backing off while the
+    /// backend keeps failing.
+    fn refresh_due(&self, ttl: std::time::Duration, now: std::time::Instant) -> bool {
+        if self.refreshing {
+            return false;
+        }
+        let (since, interval) = match (self.failed_refreshes, self.entry) {
+            (Some((failed_at, failures)), _) => {
+                let backoff = ttl.saturating_mul(1 << (failures - 1).min(16));
+                (failed_at, backoff.min(MAX_RETRY_INTERVAL.max(ttl)))
+            }
+            (None, Some((_, fetched_at))) => (fetched_at, ttl),
+            (None, None) => return false,
+        };
+        now.duration_since(since) >= interval
+    }
+
+    fn record_failure(&mut self, now: std::time::Instant) {
+        self.stale = true;
+        let failures = self
+            .failed_refreshes
+            .map_or(1, |(_, failures)| failures.saturating_add(1));
+        self.failed_refreshes = Some((now, failures));
+    }
+}
+
+/// Serves the config from memory for `ttl` after each successful read. Once
+/// it expires the cached config is still returned while a background thread
+/// reads a fresh one, and it keeps being returned if that read fails. Only the
+/// very first read goes to the backend directly and can fail the caller.
+/// Failed refreshes are reported to subscribers as `ConfigStale` and retried
+/// less and less often while the backend stays down.
+pub struct CachingConfigReader {
+    reader: std::sync::Arc<dyn ReadConfig + Send + Sync>,
+    ttl: std::time::Duration,
+    clock: std::sync::Arc<dyn Clock>,
+    state: std::sync::Arc<std::sync::Mutex<CacheState>>,
+    subscribers: std::sync::Arc<std::sync::Mutex<Vec<ReloadSubscriber>>>,
+}
+
+impl CachingConfigReader {
+    pub fn new(reader: Box<dyn ReadConfig + Send + Sync>, ttl: std::time::Duration) -> Self {
+        CachingConfigReader {
+            reader: reader.into(),
+            ttl,
+            clock: std::sync::Arc::new(SystemClock::default()),
+            state: std::sync::Arc::new(std::sync::Mutex::new(CacheState::default())),
+            subscribers: std::sync::Arc::new(std::sync::Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: std::sync::Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Subscribers are called from the refresh thread.
+    pub fn subscribe(&self, subscriber: ReloadSubscriber) {
+        self.subscribers.lock().unwrap().push(subscriber);
+    }
+
+    pub fn get_cache_status(&self) -> CacheStatus {
+        let state = self.state.lock().unwrap();
+        CacheStatus {
+            age: state
+                .entry
+                .map(|(_, fetched_at)| self.clock.now().duration_since(fetched_at)),
+            stale: state.stale,
+            refreshing: state.refreshing,
+        }
+    }
+
+    fn start_refresh(&self) -> Result<(), ConfigError> {
+        let reader = self.reader.clone();
+        let clock = self.clock.clone();
+        let state = self.state.clone();
+        let subscribers = self.subscribers.clone();
+        std::thread::Builder::new()
+            .name("config-refresh".to_string())
+            .spawn(move || {
+                let result = reader.get_config();
+                let stale = {
+                    let mut state = state.lock().unwrap();
+                    match result {
+                        Ok(config) => {
+                            state.entry = Some((config, clock.now()));
+                            state.stale = false;
+                            state.failed_refreshes = None;
+                            None
+                        }
+                        Err(error) => {
+                            state.record_failure(clock.now());
+                            let age = state
+                                .entry
+                                .map_or(std::time::Duration::ZERO, |(_, fetched_at)| {
+                                    clock.now().duration_since(fetched_at)
+                                });
+                            Some((error, age))
+                        }
+                    }
+                };
+                if let Some((error, age)) = stale {
+                    for subscriber in subscribers.lock().unwrap().iter_mut() {
+                        subscriber(&ControllerEvent::ConfigStale { error: &error, age });
+                    }
+                }
+                state.lock().unwrap().refreshing = false;
+            })
+            .map(|_| ())
+            .map_err(|err| ConfigError::Unavailable(Box::new(err)))
+    }
+}
+
+impl ReadConfig for CachingConfigReader {
+    fn get_config(&self) -> Result<Config, ConfigError> {
+        let entry = self.state.lock().unwrap().entry;
+        let config = match entry {
+            Some((config, _)) => config,
+            None => {
+                // Not locked while reading, so a slow backend does not block
+                // `get_cache_status`.
+                let config = self.reader.get_config()?;
+                let mut state = self.state.lock().unwrap();
+                let (config, _) = *state.entry.get_or_insert((config, self.clock.now()));
+                return Ok(config);
+            }
+        };
+        let mut state = self.state.lock().unwrap();
+        if state.refresh_due(self.ttl, self.clock.now()) {
+            state.refreshing = true;
+            drop(state);
+            if self.start_refresh().is_err() {
+                let mut state = self.state.lock().unwrap();
+                state.refreshing = false;
+                state.record_failure(self.clock.now());
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::config_reader::MockReadConfig;
+
+    fn wait_for_refresh(reader: &CachingConfigReader) {
+        for _ in 0..200 {
+            if !reader.get_cache_status().refreshing {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        panic!("the config was not refreshed");
+    }
+
+    #[test]
+    fn serves_cached_config_within_ttl() {
+        let mut config_reader_mock = MockReadConfig::new();
+        config_reader_mock
+            .expect_get_config()
+            .times(1)
+            .returning(|| Ok(Config::default()));
+
+        let clock = std::sync::Arc::new(ManualClock::default());
+        let reader = CachingConfigReader::new(
+            Box::new(config_reader_mock),
+            std::time::Duration::from_secs(60),
+        )
+        .with_clock(clock.clone());
+
+        assert!(reader.get_cache_status().age.is_none());
+        assert!(reader.get_config().is_ok());
+        clock.advance(std::time::Duration::from_secs(59));
+        assert!(reader.get_config().is_ok());
+        assert!(reader.get_cache_status().age == Some(std::time::Duration::from_secs(59)));
+    }
+
+    #[test]
+    fn refreshes_in_background_and_serves_stale_on_error() {
+        let mut config_reader_mock = MockReadConfig::new();
+        // Reads succeed, then the backend goes away.
+        let reads = std::sync::atomic::AtomicUsize::new(0);
+        config_reader_mock.expect_get_config().returning(move || {
+            match reads.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 => Ok(Config::default()),
+                1 => Ok(Config {
+                    setpoint: 18f32,
+                    ..Config::default()
+                }),
+                _ => Err(ConfigError::Missing(None)),
+            }
+        });
+
+        let clock = std::sync::Arc::new(ManualClock::default());
+        let reader = CachingConfigReader::new(
+            Box::new(config_reader_mock),
+            std::time::Duration::from_secs(60),
+        )
+        .with_clock(clock.clone());
+        let stale_ages = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen_stale_ages = stale_ages.clone();
+        reader.subscribe(Box::new(move |event| {
+            if let ControllerEvent::ConfigStale { age, .. } = event {
+                seen_stale_ages.lock().unwrap().push(*age);
+            }
+        }));
+        assert!(reader.get_config().is_ok());
+
+        // The expired config is served while the refresh runs.
+        clock.advance(std::time::Duration::from_secs(60));
+        assert!(matches!(reader.get_config(), Ok(config) if config.setpoint == 21f32));
+        wait_for_refresh(&reader);
+        assert!(matches!(reader.get_config(), Ok(config) if config.setpoint == 18f32));
+        assert!(reader.get_cache_status().age == Some(std::time::Duration::ZERO));
+
+        clock.advance(std::time::Duration::from_secs(60));
+        assert!(reader.get_config().is_ok());
+        wait_for_refresh(&reader);
+        assert!(matches!(reader.get_config(), Ok(config) if config.setpoint == 18f32));
+        let status = reader.get_cache_status();
+        assert!(status.stale);
+        assert!(status.age == Some(std::time::Duration::from_secs(60)));
+        assert!(*stale_ages.lock().unwrap() == vec![std::time::Duration::from_secs(60)]);
+    }
+
+    #[test]
+    fn failed_refreshes_back_off() {
+        let mut config_reader_mock = MockReadConfig::new();
+        let reads = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let backend_reads = reads.clone();
+        config_reader_mock.expect_get_config().returning(move || {
+            match backend_reads.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 => Ok(Config::default()),
+                _ => Err(ConfigError::Missing(None)),
+            }
+        });
+
+        let clock = std::sync::Arc::new(ManualClock::default());
+        let reader = CachingConfigReader::new(
+            Box::new(config_reader_mock),
+            std::time::Duration::from_secs(60),
+        )
+        .with_clock(clock.clone());
+        let reads_after = |seconds| {
+            clock.advance(std::time::Duration::from_secs(seconds));
+            assert!(reader.get_config().is_ok());
+            wait_for_refresh(&reader);
+            reads.load(std::sync::atomic::Ordering::SeqCst)
+        };
+
+        assert!(reads_after(0) == 1);
+        assert!(reads_after(60) == 2);
+        // Retried a TTL after the failure, then twice that.
+        assert!(reads_after(1) == 2);
+        assert!(reads_after(59) == 3);
+        assert!(reads_after(60) == 3);
+        assert!(reads_after(60) == 4);
+    }
+
+    #[test]
+    fn first_read_error_is_returned() {
+        let mut config_reader_mock = MockReadConfig::new();
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Err(ConfigError::Missing(None)));
+
+        let reader = CachingConfigReader::new(Box::new(config_reader_mock), DEFAULT_TTL);
+
+        assert!(matches!(
+            reader.get_config(),
+            Err(ConfigError::Missing(None))
+        ));
+    }
+}
@@ -1,33 +1,133 @@
 use mysql::prelude::Queryable;
 
-use super::{ReadConfig, Config};
+use super::validation::validate;
+use super::{config_from_fields, Config, ConfigError, ReadConfig, WriteConfig};
+use crate::control_strategy::pid::PidGains;
+use crate::setpoint_schedule::{add_periods, parse_timezone, ReadSchedule, WeeklySchedule};
 
+/// Connects on first use rather than when built, so a database that is down
+/// at startup only fails the reads until it comes back.
 pub struct ConfigSqlReader {
-    pool: mysql::Pool,
+    opts: mysql::Opts,
+    pool: std::sync::Mutex<Option<mysql::Pool>>,
+    zone: Option<String>,
 }
 
 impl ConfigSqlReader {
-    pub fn build(sql_connection_string: String) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn build(sql_connection_string: String) -> Result<Self, ConfigError> {
         let opts = mysql::Opts::from_url(&sql_connection_string)?;
-        let pool = mysql::Pool::new(opts)?;
-        Ok(ConfigSqlReader { pool })
+        Ok(ConfigSqlReader {
+            opts,
+            pool: std::sync::Mutex::new(None),
+            zone: None,
+        })
+    }
+
+    fn get_conn(&self) -> Result<mysql::PooledConn, ConfigError> {
+        let existing = self.pool.lock().unwrap().clone();
+        let pool = match existing {
+            Some(pool) => pool,
+            None => {
+                // Connected without the lock so a slow server does not hold up
+                // other callers. If two connect at once the first pool is kept.
+                let created = mysql::Pool::new(self.opts.clone())?;
+                self.pool.lock().unwrap().get_or_insert(created).clone()
+            }
+        };
+        Ok(pool.get_conn()?)
+    }
+
+    /// Reads the row whose `zone` column matches `zone`.
+    pub fn build_for_zone(
+        sql_connection_string: String,
+        zone: String,
+    ) -> Result<Self, ConfigError> {
+        Ok(ConfigSqlReader {
+            zone: Some(zone),
+            ..ConfigSqlReader::build(sql_connection_string)?
+        })
     }
 }
 
+/// Reads whichever config columns the table has, so tables that only carry
+/// `min_temperature`/`max_temperature` keep working.
+fn config_from_row(row: &mysql::Row) -> Result<Config, ConfigError> {
+    config_from_fields(|column| {
+        let index = row
+            .columns_ref()
+            .iter()
+            .position(|candidate| candidate.name_str() == column)?;
+        match row.as_ref(index)? {
+            mysql::Value::NULL => None,
+            mysql::Value::Bytes(bytes) => Some(String::from_utf8_lossy(bytes).into_owned()),
+            value => Some(value.as_sql(true)),
+        }
+    })
+}
+
+impl From<mysql::Error> for ConfigError {
+    fn from(err: mysql::Error) -> Self {
+        ConfigError::Unavailable(Box::new(err))
+    }
+}
+
+impl From<mysql::UrlError> for ConfigError {
+    fn from(err: mysql::UrlError) -> Self {
+        ConfigError::Unavailable(Box::new(err))
+    }
+}
 
 impl ReadConfig for ConfigSqlReader {
-    fn get_config(&self) -> Result<Option<Config>, Box<dyn std::error::Error>> {
-        let mut conn = self.pool.get_conn()?;
-        let result = conn.query_map(
-            r#"SELECT min_temperature, max_temperature FROM Config"#,
-            |(min_temperature, max_temperature)| Config {
-                min_temperature,
-                max_temperature,
-            },
-        )?;
-        if result.len() != 0 {
-            return Ok(Some(result[0]));
+    fn get_config(&self) -> Result<Config, ConfigError> {
+        let mut conn = self.get_conn()?;
+        let row: Option<mysql::Row> = match &self.zone {
+            Some(zone) => conn.exec_first(r#"SELECT * FROM Config WHERE zone = ?"#, (zone,))?,
+            None => conn.query_first(r#"SELECT * FROM Config"#)?,
+        };
+        match row {
+            Some(row) => validate(config_from_row(&row)?, |_| None),
+            None => Err(ConfigError::Missing(None)),
+        }
+    }
+}
+
+/// Needs `pid_kp`, `pid_ki` and `pid_kd` columns on the `Config` table.
+impl WriteConfig for ConfigSqlReader {
+    fn save_pid_gains(&self, gains: &PidGains) -> Result<(), ConfigError> {
+        const UPDATE: &str = r#"UPDATE Config SET pid_kp = ?, pid_ki = ?, pid_kd = ?"#;
+        let mut conn = self.get_conn()?;
+        match &self.zone {
+            Some(zone) => conn.exec_drop(
+                format!("{UPDATE} WHERE zone = ?"),
+                (gains.kp, gains.ki, gains.kd, zone),
+            )?,
+            None => conn.exec_drop(UPDATE, (gains.kp, gains.ki, gains.kd))?,
+        }
+        Ok(())
+    }
+}
+
+type ScheduleRow = (String, String, String, String, String);
+
+/// Reads the `Schedule` table, one row per period with `weekday`, `start_time`,
+/// `name`, `setpoint` and `timezone` columns. `weekday` takes the same values
+/// as the config file, e.g. `mon` or `weekdays`.
+impl ReadSchedule for ConfigSqlReader {
+    fn get_schedule(&self) -> Result<Option<WeeklySchedule>, ConfigError> {
+        const COLUMNS: &str = r#"SELECT weekday, TIME_FORMAT(start_time, '%H:%i'), name, CAST(setpoint AS CHAR), timezone FROM Schedule"#;
+        let mut conn = self.get_conn()?;
+        let rows: Vec<ScheduleRow> = match &self.zone {
+            Some(zone) => conn.exec(format!("{COLUMNS} WHERE zone = ?"), (zone,))?,
+            None => conn.query(COLUMNS)?,
+        };
+        let timezone = match rows.first() {
+            Some((.., timezone)) => parse_timezone(timezone)?,
+            None => return Ok(None),
+        };
+        let mut schedule = WeeklySchedule::new(timezone);
+        for (weekday, start, name, setpoint, _) in rows.iter() {
+            add_periods(&mut schedule, weekday, start, name, setpoint)?;
         }
-        Ok(None)
+        Ok(Some(schedule))
     }
 }
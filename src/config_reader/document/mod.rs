@@ -0,0 +1,318 @@
+use super::validation::{validate, Location};
+use super::{config_from_fields, Config, ConfigError};
+use crate::setpoint_schedule::{add_periods, parse_date, parse_timezone, WeeklySchedule};
+
+/// Section names a structured config file may contain.
+pub const SECTIONS: [&str; 6] = ["limits", "setpoint", "mode", "schedule", "sensors", "pid"];
+
+/// Structured config with named sections, shared by the formats that have
+/// them, e.g.
+///
+/// ```toml
+/// [setpoint]
+/// value = 21.5
+/// ramp_rate = 0.5 # degrees per minute
+///
+/// [limits]
+/// heating_deadband = 0.5
+/// min_run_time = "5m"
+/// ```
+///
+/// Unknown keys are rejected rather than silently ignored.
+#[derive(serde::Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigDocument {
+    #[serde(default)]
+    pub setpoint: SetpointSection,
+    #[serde(default)]
+    pub limits: LimitsSection,
+    #[serde(default)]
+    pub mode: ModeSection,
+    #[serde(default)]
+    pub sensors: SensorsSection,
+    #[serde(default)]
+    pub pid: PidSection,
+    pub schedule: Option<ScheduleSection>,
+}
+
+#[derive(serde::Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SetpointSection {
+    pub value: Option<f32>,
+    pub ramp_rate: Option<f32>,
+}
+
+/// Either a setpoint or both `min_temperature` and `max_temperature` are
+/// needed, as for the one-line format.
+#[derive(serde::Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LimitsSection {
+    pub min_temperature: Option<f32>,
+    pub max_temperature: Option<f32>,
+    pub heating_deadband: Option<f32>,
+    pub cooling_deadband: Option<f32>,
+    pub heating_overshoot: Option<f32>,
+    pub cooling_overshoot: Option<f32>,
+    pub min_run_time: Option<DurationValue>,
+    pub min_off_time: Option<DurationValue>,
+    pub min_changeover_delay: Option<DurationValue>,
+}
+
+#[derive(serde::Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ModeSection {
+    pub current: Option<String>,
+    pub eco_offset: Option<f32>,
+    pub away_offset: Option<f32>,
+}
+
+#[derive(serde::Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SensorsSection {
+    pub sample_period: Option<DurationValue>,
+    pub control_period: Option<DurationValue>,
+}
+
+#[derive(serde::Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PidSection {
+    pub kp: Option<f32>,
+    pub ki: Option<f32>,
+    pub kd: Option<f32>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleSection {
+    pub timezone: String,
+    #[serde(default)]
+    pub holidays: Vec<String>,
+    #[serde(default)]
+    pub periods: Vec<PeriodEntry>,
+}
+
+/// `days` takes the same values as the one-line schedule, e.g. `weekdays`.
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PeriodEntry {
+    pub days: String,
+    pub start: String,
+    pub name: String,
+    pub setpoint: f32,
+}
+
+/// Seconds, or a number with an `ms`, `s`, `m` or `h` unit such as `"5m"`.
+#[derive(serde::Deserialize, Clone, PartialEq, Debug)]
+#[serde(untagged)]
+pub enum DurationValue {
+    Seconds(f32),
+    WithUnit(String),
+}
+
+impl DurationValue {
+    pub fn seconds(&self, name: &str) -> Result<f32, ConfigError> {
+        let text = match self {
+            DurationValue::Seconds(seconds) => return Ok(*seconds),
+            DurationValue::WithUnit(text) => text.trim(),
+        };
+        let split = text
+            .find(|character: char| character.is_ascii_alphabetic())
+            .unwrap_or(text.len());
+        let (number, unit) = text.split_at(split);
+        let scale = match unit.trim() {
+            "ms" => 0.001,
+            "" | "s" => 1.0,
+            "m" | "min" => 60.0,
+            "h" => 3600.0,
+            unit => {
+                return Err(ConfigError::Invalid(format!(
+                    "Unknown unit {unit} for {name}"
+                )))
+            }
+        };
+        let number = number.trim().parse::<f32>().map_err(|err| {
+            ConfigError::Invalid(format!("Failed to convert {name} to a number: {err}"))
+        })?;
+        Ok(number * scale)
+    }
+}
+
+/// Parses `contents` with a serde format, taking the document nested under
+/// `zone` when one is given. Empty contents count as a missing config.
+pub fn parse_document<E: std::fmt::Display>(
+    contents: &str,
+    zone: Option<&str>,
+    format: &str,
+    parse: impl FnOnce(&str) -> Result<ConfigDocument, E>,
+    parse_zones: impl FnOnce(&str) -> Result<std::collections::HashMap<String, ConfigDocument>, E>,
+) -> Result<ConfigDocument, ConfigError> {
+    if contents.trim().is_empty() {
+        return Err(ConfigError::Missing(None));
+    }
+    let invalid = |err: E| ConfigError::Invalid(format!("Failed to parse {format} config: {err}"));
+    match zone {
+        Some(zone) => parse_zones(contents)
+            .map_err(invalid)?
+            .remove(zone)
+            .ok_or(ConfigError::Missing(None)),
+        None => parse(contents).map_err(invalid),
+    }
+}
+
+impl ConfigDocument {
+    /// Builds the config the same way the one-line format does, so defaults
+    /// and errors match.
+    pub fn to_config(&self) -> Result<Config, ConfigError> {
+        let mut fields = std::collections::HashMap::new();
+        let mut number = |name: &'static str, value: Option<f32>| {
+            if let Some(value) = value {
+                fields.insert(name, value.to_string());
+            }
+        };
+        number("setpoint", self.setpoint.value);
+        number("setpoint_ramp_rate", self.setpoint.ramp_rate);
+        number("min_temperature", self.limits.min_temperature);
+        number("max_temperature", self.limits.max_temperature);
+        number("heating_deadband", self.limits.heating_deadband);
+        number("cooling_deadband", self.limits.cooling_deadband);
+        number("heating_overshoot", self.limits.heating_overshoot);
+        number("cooling_overshoot", self.limits.cooling_overshoot);
+        number("eco_offset", self.mode.eco_offset);
+        number("away_offset", self.mode.away_offset);
+        number("pid_kp", self.pid.kp);
+        number("pid_ki", self.pid.ki);
+        number("pid_kd", self.pid.kd);
+        for (name, value) in [
+            ("min_run_time", &self.limits.min_run_time),
+            ("min_off_time", &self.limits.min_off_time),
+            ("min_changeover_delay", &self.limits.min_changeover_delay),
+            ("sample_period", &self.sensors.sample_period),
+            ("control_period", &self.sensors.control_period),
+        ] {
+            if let Some(value) = value {
+                fields.insert(name, value.seconds(name)?.to_string());
+            }
+        }
+        if let Some(mode) = &self.mode.current {
+            fields.insert("mode", mode.clone());
+        }
+        config_from_fields(|name| fields.get(name).cloned())
+    }
+
+    /// The validated config, with diagnostics pointing into `contents`, the
+    /// text this document was parsed from.
+    pub fn to_validated_config(
+        &self,
+        contents: &str,
+        zone: Option<&str>,
+    ) -> Result<Config, ConfigError> {
+        validate(self.to_config()?, |field| {
+            locate_field(contents, zone, field)
+        })
+    }
+
+    pub fn to_schedule(&self) -> Result<Option<WeeklySchedule>, ConfigError> {
+        let section = match &self.schedule {
+            Some(section) => section,
+            None => return Ok(None),
+        };
+        let mut schedule = WeeklySchedule::new(parse_timezone(&section.timezone)?);
+        for period in section.periods.iter() {
+            add_periods(
+                &mut schedule,
+                &period.days,
+                &period.start,
+                &period.name,
+                &period.setpoint.to_string(),
+            )?;
+        }
+        for holiday in section.holidays.iter() {
+            schedule.add_holiday(parse_date(holiday)?);
+        }
+        Ok(Some(schedule))
+    }
+}
+
+/// The section and key a config field is set by.
+fn field_path(field: &str) -> Option<(&'static str, &str)> {
+    match field {
+        "setpoint" => Some(("setpoint", "value")),
+        "setpoint_ramp_rate" => Some(("setpoint", "ramp_rate")),
+        "mode" => Some(("mode", "current")),
+        "eco_offset" | "away_offset" => Some(("mode", field)),
+        "sample_period" | "control_period" => Some(("sensors", field)),
+        "pid_kp" => Some(("pid", "kp")),
+        "pid_ki" => Some(("pid", "ki")),
+        "pid_kd" => Some(("pid", "kd")),
+        "min_temperature"
+        | "max_temperature"
+        | "heating_deadband"
+        | "cooling_deadband"
+        | "heating_overshoot"
+        | "cooling_overshoot"
+        | "min_run_time"
+        | "min_off_time"
+        | "min_changeover_delay" => Some(("limits", field)),
+        _ => None,
+    }
+}
+
+/// Finds `field` by looking for its key after its section, and after the
+/// zone for zoned files. This works on the text, so it suits every format
+/// the document is read from.
+fn locate_field(contents: &str, zone: Option<&str>, field: &str) -> Option<Location> {
+    let (section, key) = field_path(field)?;
+    let is_word = |character: char| character.is_alphanumeric() || character == '_';
+    let (mut found, mut offset) = (0, 0);
+    for word in zone.into_iter().chain([section, key]) {
+        found = contents[offset..]
+            .match_indices(word)
+            .map(|(index, _)| offset + index)
+            .find(|&index| {
+                !contents[..index].ends_with(is_word)
+                    && !contents[index + word.len()..].starts_with(is_word)
+            })?;
+        offset = found + word.len();
+    }
+    Some(Location::of_offset(contents, found))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_take_units() {
+        let duration = |value: DurationValue| value.seconds("min_run_time");
+
+        assert!(matches!(duration(DurationValue::Seconds(90.0)), Ok(seconds) if seconds == 90.0));
+        assert!(
+            matches!(duration(DurationValue::WithUnit("5m".to_string())), Ok(seconds) if seconds == 300.0)
+        );
+        assert!(
+            matches!(duration(DurationValue::WithUnit("1.5 h".to_string())), Ok(seconds) if seconds == 5400.0)
+        );
+        assert!(matches!(
+            duration(DurationValue::WithUnit("3 days".to_string())),
+            Err(ConfigError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn partial_pid_gains_are_rejected() {
+        let document = ConfigDocument {
+            setpoint: SetpointSection {
+                value: Some(20.0),
+                ramp_rate: None,
+            },
+            pid: PidSection {
+                kp: Some(1.0),
+                ki: None,
+                kd: None,
+            },
+            ..ConfigDocument::default()
+        };
+
+        assert!(matches!(document.to_config(), Err(ConfigError::Invalid(_))));
+    }
+}
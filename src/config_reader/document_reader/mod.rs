@@ -0,0 +1,203 @@
+use super::document::{parse_document, ConfigDocument};
+use super::{Config, ConfigError, ReadConfig};
+use crate::setpoint_schedule::{ReadSchedule, WeeklySchedule};
+
+/// A serde format a `ConfigDocument` can be written in.
+pub trait DocumentFormat {
+    const NAME: &'static str;
+    type Error: std::fmt::Display;
+
+    fn from_str<T: serde::de::DeserializeOwned>(contents: &str) -> Result<T, Self::Error>;
+}
+
+/// JSON, with one object per zone for zoned files.
+pub struct Json;
+
+impl DocumentFormat for Json {
+    const NAME: &'static str = "JSON";
+    type Error = serde_json::Error;
+
+    fn from_str<T: serde::de::DeserializeOwned>(contents: &str) -> Result<T, Self::Error> {
+        serde_json::from_str(contents)
+    }
+}
+
+/// YAML, with one mapping per zone for zoned files.
+pub struct Yaml;
+
+impl DocumentFormat for Yaml {
+    const NAME: &'static str = "YAML";
+    type Error = serde_yaml::Error;
+
+    fn from_str<T: serde::de::DeserializeOwned>(contents: &str) -> Result<T, Self::Error> {
+        serde_yaml::from_str(contents)
+    }
+}
+
+pub type ConfigJsonReader = ConfigDocumentReader<Json>;
+pub type ConfigYamlReader = ConfigDocumentReader<Yaml>;
+
+/// Reads a file laid out as a `ConfigDocument` in the format `F`.
+pub struct ConfigDocumentReader<F> {
+    config_file_name: String,
+    zone: Option<String>,
+    format: std::marker::PhantomData<F>,
+}
+
+impl<F: DocumentFormat> ConfigDocumentReader<F> {
+    pub fn new(config_file_name: String) -> Self {
+        ConfigDocumentReader {
+            config_file_name,
+            zone: None,
+            format: std::marker::PhantomData,
+        }
+    }
+
+    pub fn for_zone(config_file_name: String, zone: String) -> Self {
+        ConfigDocumentReader {
+            config_file_name,
+            zone: Some(zone),
+            format: std::marker::PhantomData,
+        }
+    }
+
+    fn read_document(&self) -> Result<(String, ConfigDocument), ConfigError> {
+        let contents = std::fs::read_to_string(&self.config_file_name)?;
+        let document = parse_document(
+            &contents,
+            self.zone.as_deref(),
+            F::NAME,
+            F::from_str,
+            F::from_str,
+        )?;
+        Ok((contents, document))
+    }
+}
+
+impl<F: DocumentFormat> ReadConfig for ConfigDocumentReader<F> {
+    fn get_config(&self) -> Result<Config, ConfigError> {
+        let (contents, document) = self.read_document()?;
+        document.to_validated_config(&contents, self.zone.as_deref())
+    }
+}
+
+impl<F: DocumentFormat> ReadSchedule for ConfigDocumentReader<F> {
+    fn get_schedule(&self) -> Result<Option<WeeklySchedule>, ConfigError> {
+        self.read_document()?.1.to_schedule()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_reader::toml_reader::ConfigTomlReader;
+
+    #[test]
+    fn json_matches_toml() {
+        let config_reader = ConfigJsonReader::new("test_configs/config.json".to_string());
+        let expected_config =
+            ConfigTomlReader::new("test_configs/config.toml".to_string()).get_config();
+        assert!(expected_config.is_ok());
+
+        let config = config_reader.get_config();
+        assert!(config.is_ok());
+        assert!(config.unwrap() == expected_config.unwrap());
+
+        let schedule = config_reader.get_schedule();
+        assert!(schedule.is_ok());
+        let schedule = schedule.unwrap();
+        assert!(schedule.is_some());
+        assert!(schedule.unwrap().periods().len() == 7);
+    }
+
+    #[test]
+    fn missing_setpoint() {
+        let config_reader =
+            ConfigJsonReader::new("test_configs/missing_setpoint_config.json".to_string());
+
+        let config = config_reader.get_config();
+        assert!(
+            matches!(config, Err(ConfigError::Invalid(message)) if message.starts_with("Config needs either a setpoint"))
+        );
+    }
+
+    #[test]
+    fn invalid_field_reports_position() {
+        let config_reader =
+            ConfigJsonReader::new("test_configs/invalid_field_config.json".to_string());
+
+        let config = config_reader.get_config();
+        assert!(matches!(config, Err(ConfigError::Invalid(message)) if message.contains("line 3")));
+    }
+
+    #[test]
+    fn missing_json_file() {
+        let config_reader = ConfigJsonReader::new("test_configs/missing_file".to_string());
+
+        let config = config_reader.get_config();
+        assert!(matches!(config, Err(ConfigError::Missing(Some(_)))));
+    }
+
+    #[test]
+    fn empty_json_file() {
+        let config_reader = ConfigJsonReader::new("test_configs/empty_config.txt".to_string());
+
+        let config = config_reader.get_config();
+        assert!(matches!(config, Err(ConfigError::Missing(None))));
+    }
+
+    #[test]
+    fn yaml_matches_toml() {
+        let config_reader = ConfigYamlReader::new("test_configs/config.yaml".to_string());
+        let expected_config =
+            ConfigTomlReader::new("test_configs/config.toml".to_string()).get_config();
+        assert!(expected_config.is_ok());
+
+        let config = config_reader.get_config();
+        assert!(config.is_ok());
+        assert!(config.unwrap() == expected_config.unwrap());
+
+        let schedule = config_reader.get_schedule();
+        assert!(schedule.is_ok());
+        let schedule = schedule.unwrap();
+        assert!(schedule.is_some());
+        assert!(schedule.unwrap().timezone() == chrono_tz::Europe::Berlin);
+    }
+
+    #[test]
+    fn yaml_zone() {
+        let config_reader = ConfigYamlReader::for_zone(
+            "test_configs/zones_config.yaml".to_string(),
+            "kitchen".to_string(),
+        );
+
+        let config = config_reader.get_config();
+        assert!(config.is_ok());
+        assert!(float_cmp::approx_eq!(
+            f32,
+            config.unwrap().min_temperature(),
+            -5f32,
+            epsilon = 0.000001
+        ));
+
+        let config_reader = ConfigYamlReader::for_zone(
+            "test_configs/zones_config.yaml".to_string(),
+            "attic".to_string(),
+        );
+        assert!(matches!(
+            config_reader.get_config(),
+            Err(ConfigError::Missing(None))
+        ));
+    }
+
+    #[test]
+    fn invalid_mode() {
+        let config_reader =
+            ConfigYamlReader::new("test_configs/invalid_mode_config.yaml".to_string());
+
+        let config = config_reader.get_config();
+        assert!(
+            matches!(config, Err(ConfigError::Invalid(message)) if message.starts_with("Failed to convert mode"))
+        );
+    }
+}
@@ -1,114 +1,472 @@
 use std::io::BufRead;
 
-use super::{ReadConfig, Config, extract_config_from_line};
+use super::toml_reader::{is_toml, ConfigTomlReader};
+use super::validation::validate;
+use super::{
+    extract_config_from_line, line_with_pid_gains, locate_in_line, Config, ConfigError, ReadConfig,
+    WriteConfig,
+};
+use crate::control_strategy::pid::PidGains;
+use crate::setpoint_schedule::{parse_schedule_lines, ReadSchedule, WeeklySchedule};
 
+/// Reads the legacy one-line format, or hands over to `ConfigTomlReader`
+/// when the file has a `.toml` extension or its contents are TOML.
 pub struct ConfigFileReader {
     config_file_name: String,
+    zone: Option<String>,
 }
 
 impl ConfigFileReader {
     pub fn new(config_file_name: String) -> Self {
-        ConfigFileReader { config_file_name }
+        ConfigFileReader {
+            config_file_name,
+            zone: None,
+        }
+    }
+
+    /// Reads the config line under the `[<zone>]` header of a file holding one
+    /// section per zone.
+    pub fn for_zone(config_file_name: String, zone: String) -> Self {
+        ConfigFileReader {
+            config_file_name,
+            zone: Some(zone),
+        }
+    }
+
+    /// A TOML reader for the same file and zone if the file is TOML.
+    fn toml_reader(&self) -> Result<Option<ConfigTomlReader>, ConfigError> {
+        let toml_file = self.config_file_name.ends_with(".toml")
+            || is_toml(&std::fs::read_to_string(&self.config_file_name)?);
+        if !toml_file {
+            return Ok(None);
+        }
+        Ok(Some(match &self.zone {
+            Some(zone) => ConfigTomlReader::for_zone(self.config_file_name.clone(), zone.clone()),
+            None => ConfigTomlReader::new(self.config_file_name.clone()),
+        }))
     }
-}
 
+    /// The non-empty lines under the `[<section>]` header with their 1-based
+    /// line numbers.
+    fn read_section(&self, section: &str) -> Result<Vec<(usize, String)>, ConfigError> {
+        let buffer_reader = std::io::BufReader::new(std::fs::File::open(&self.config_file_name)?);
+        let header = format!("[{section}]");
+        let mut in_section = false;
+        let mut lines = Vec::new();
+        for (index, line) in buffer_reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.starts_with('[') {
+                in_section = line == header;
+            } else if in_section && !line.is_empty() {
+                lines.push((index + 1, line.to_string()));
+            }
+        }
+        Ok(lines)
+    }
+}
 
 impl ReadConfig for ConfigFileReader {
-    fn get_config(&self) -> Result<Option<Config>, Box<dyn std::error::Error>> {
-        let mut buffer_reader =
-            std::io::BufReader::new(std::fs::File::open(&self.config_file_name)?);
-        let mut buffer = String::new();
-        buffer_reader.read_line(&mut buffer)?;
-        Ok(extract_config_from_line(&buffer))
+    fn get_config(&self) -> Result<Config, ConfigError> {
+        if let Some(toml_reader) = self.toml_reader()? {
+            return toml_reader.get_config();
+        }
+        let (line_number, buffer) = match &self.zone {
+            Some(zone) => self
+                .read_section(zone)?
+                .into_iter()
+                .next()
+                .unwrap_or_default(),
+            None => {
+                let mut buffer_reader =
+                    std::io::BufReader::new(std::fs::File::open(&self.config_file_name)?);
+                let mut buffer = String::new();
+                buffer_reader.read_line(&mut buffer)?;
+                (1, buffer)
+            }
+        };
+        if buffer.trim().is_empty() {
+            return Err(ConfigError::Missing(None));
+        }
+        validate(extract_config_from_line(&buffer)?, |field| {
+            locate_in_line(&buffer, line_number, field)
+        })
     }
 }
 
+/// Rewrites the line `get_config` reads in place, keeping every other line.
+impl WriteConfig for ConfigFileReader {
+    fn save_pid_gains(&self, gains: &PidGains) -> Result<(), ConfigError> {
+        if let Some(toml_reader) = self.toml_reader()? {
+            return toml_reader.save_pid_gains(gains);
+        }
+        let contents = std::fs::read_to_string(&self.config_file_name)?;
+        let mut lines = contents
+            .lines()
+            .map(str::to_string)
+            .collect::<Vec<String>>();
+        let index = match &self.zone {
+            Some(zone) => {
+                let header = format!("[{zone}]");
+                lines
+                    .iter()
+                    .position(|line| line.trim() == header)
+                    .and_then(|start| {
+                        lines
+                            .iter()
+                            .enumerate()
+                            .skip(start + 1)
+                            .take_while(|(_, line)| !line.trim().starts_with('['))
+                            .find(|(_, line)| !line.trim().is_empty())
+                            .map(|(index, _)| index)
+                    })
+            }
+            None => (!lines.is_empty()).then_some(0),
+        };
+        let index = match index {
+            Some(index) if !lines[index].trim().is_empty() => index,
+            _ => return Err(ConfigError::Missing(None)),
+        };
+        lines[index] = line_with_pid_gains(&lines[index], gains)?;
+        let mut contents = lines.join("\n");
+        contents.push('\n');
+        std::fs::write(&self.config_file_name, contents)?;
+        Ok(())
+    }
+}
+
+/// The schedule lives in a `[schedule]` section, or `[<zone>.schedule]` for
+/// a zone.
+impl ReadSchedule for ConfigFileReader {
+    fn get_schedule(&self) -> Result<Option<WeeklySchedule>, ConfigError> {
+        if let Some(toml_reader) = self.toml_reader()? {
+            return toml_reader.get_schedule();
+        }
+        let section = match &self.zone {
+            Some(zone) => format!("{zone}.schedule"),
+            None => "schedule".to_string(),
+        };
+        let lines = self.read_section(&section)?;
+        if lines.is_empty() {
+            return Ok(None);
+        }
+        parse_schedule_lines(lines.iter().map(|(_, line)| line.as_str())).map(Some)
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config_reader::validation::Location;
+    use crate::config_reader::Mode;
 
     #[test]
     fn correct_config() {
         let correct_config_file_path = "test_configs/correct_config.txt";
-        let config_reader = ConfigFileReader {
-            config_file_name: correct_config_file_path.to_string(),
-        };
+        let config_reader = ConfigFileReader::new(correct_config_file_path.to_string());
         let expected_min_temperature = -9.0;
         let expected_max_temperature = 15.0;
 
         let config = config_reader.get_config();
         assert!(config.is_ok());
         let config = config.unwrap();
-        assert!(config.is_some());
-        let config = config.unwrap();
         assert!(float_cmp::approx_eq!(
             f32,
-            config.min_temperature,
+            config.min_temperature(),
             expected_min_temperature,
             epsilon = 0.000001
         ));
         assert!(float_cmp::approx_eq!(
             f32,
-            config.max_temperature,
+            config.max_temperature(),
             expected_max_temperature,
             epsilon = 0.000001
         ));
     }
 
+    #[test]
+    fn setpoint_config() {
+        let config_file_path = "test_configs/setpoint_config.txt";
+        let config_reader = ConfigFileReader::new(config_file_path.to_string());
+        let expected_config = Config {
+            setpoint: 21.5,
+            heating_deadband: 0.5,
+            cooling_deadband: 1.5,
+            heating_overshoot: 0.25,
+            cooling_overshoot: 0.75,
+            min_run_time: std::time::Duration::from_secs(300),
+            min_off_time: std::time::Duration::from_secs(180),
+            min_changeover_delay: std::time::Duration::from_secs(600),
+            mode: Mode::Eco,
+            eco_offset: 1.5,
+            away_offset: 5.0,
+            control_period: std::time::Duration::from_secs(10),
+            sample_period: std::time::Duration::from_secs(2),
+            setpoint_ramp_rate: Some(0.5),
+            pid_gains: None,
+        };
+
+        let config = config_reader.get_config();
+        assert!(config.is_ok());
+        let config = config.unwrap();
+        assert!(config == expected_config);
+    }
+
+    #[test]
+    fn setpoint_only_config_uses_defaults() {
+        let config_file_path = "test_configs/setpoint_only_config.txt";
+        let config_reader = ConfigFileReader::new(config_file_path.to_string());
+        let expected_config = Config {
+            setpoint: 20.0,
+            ..Config::default()
+        };
+
+        let config = config_reader.get_config();
+        assert!(config.is_ok());
+        let config = config.unwrap();
+        assert!(config == expected_config);
+    }
+
+    #[test]
+    fn unknown_key_config_file() {
+        let config_file_path = "test_configs/unknown_key_config.txt";
+        let config_reader = ConfigFileReader::new(config_file_path.to_string());
+
+        let config = config_reader.get_config();
+        assert!(config.is_err());
+
+        let config_error = config.err().unwrap();
+        assert!(matches!(config_error, ConfigError::Invalid(_)));
+    }
+
     #[test]
     fn missing_config_file() {
         let config_file_path = "test_configs/missing_file";
-        let config_reader = ConfigFileReader {
-            config_file_name: config_file_path.to_string(),
-        };
+        let config_reader = ConfigFileReader::new(config_file_path.to_string());
 
         let config = config_reader.get_config();
         assert!(config.is_err());
 
         let config_error = config.err().unwrap();
-        assert!(config_error.to_string() == "No such file or directory (os error 2)");
+        assert!(matches!(config_error, ConfigError::Missing(Some(_))));
+        let source = std::error::Error::source(&config_error).unwrap();
+        assert!(source.to_string() == "No such file or directory (os error 2)");
     }
 
     #[test]
     fn partial_config_file() {
         let config_file_path = "test_configs/partial_config.txt";
-        let config_reader = ConfigFileReader {
-            config_file_name: config_file_path.to_string(),
-        };
+        let config_reader = ConfigFileReader::new(config_file_path.to_string());
 
         let config = config_reader.get_config();
-        assert!(config.is_ok());
+        assert!(config.is_err());
 
-        let config = config.unwrap();
-        assert!(config.is_none());
+        let config_error = config.err().unwrap();
+        assert!(matches!(config_error, ConfigError::Invalid(_)));
     }
 
     #[test]
     fn garbage_config_file() {
         let config_file_path = "test_configs/garbage_config.txt";
-        let config_reader = ConfigFileReader {
-            config_file_name: config_file_path.to_string(),
-        };
+        let config_reader = ConfigFileReader::new(config_file_path.to_string());
 
         let config = config_reader.get_config();
-        assert!(config.is_ok());
+        assert!(config.is_err());
 
-        let config = config.unwrap();
-        assert!(config.is_none());
+        let config_error = config.err().unwrap();
+        assert!(matches!(config_error, ConfigError::Invalid(_)));
     }
 
     #[test]
     fn empty_config_file() {
         let config_file_path = "test_configs/empty_config.txt";
-        let config_reader = ConfigFileReader {
-            config_file_name: config_file_path.to_string(),
+        let config_reader = ConfigFileReader::new(config_file_path.to_string());
+
+        let config = config_reader.get_config();
+        assert!(config.is_err());
+
+        let config_error = config.err().unwrap();
+        assert!(matches!(config_error, ConfigError::Missing(None)));
+    }
+
+    #[test]
+    fn zone_sections() {
+        let zones_config_file_path = "test_configs/zones_config.txt";
+
+        let config_reader =
+            ConfigFileReader::for_zone(zones_config_file_path.to_string(), "bedroom".to_string());
+        let config = config_reader.get_config();
+        assert!(config.is_ok());
+        let config = config.unwrap();
+        assert!(float_cmp::approx_eq!(
+            f32,
+            config.setpoint,
+            18f32,
+            epsilon = 0.000001
+        ));
+        assert!(config.mode == Mode::Eco);
+
+        let config_reader =
+            ConfigFileReader::for_zone(zones_config_file_path.to_string(), "kitchen".to_string());
+        let config = config_reader.get_config();
+        assert!(config.is_ok());
+        assert!(float_cmp::approx_eq!(
+            f32,
+            config.unwrap().min_temperature(),
+            -5f32,
+            epsilon = 0.000001
+        ));
+    }
+
+    #[test]
+    fn missing_zone_section() {
+        let zones_config_file_path = "test_configs/zones_config.txt";
+        let config_reader =
+            ConfigFileReader::for_zone(zones_config_file_path.to_string(), "attic".to_string());
+
+        let config = config_reader.get_config();
+        assert!(matches!(config, Err(ConfigError::Missing(None))));
+    }
+
+    #[test]
+    fn schedule_section() {
+        let config_file_path = "test_configs/schedule_config.txt";
+        let config_reader = ConfigFileReader::new(config_file_path.to_string());
+
+        let config = config_reader.get_config();
+        assert!(config.is_ok());
+        let schedule = config_reader.get_schedule();
+        assert!(schedule.is_ok());
+        let schedule = schedule.unwrap();
+        assert!(schedule.is_some());
+        let schedule = schedule.unwrap();
+        assert!(schedule.timezone() == chrono_tz::Europe::Berlin);
+        assert!(schedule.periods().len() == 7);
+    }
+
+    #[test]
+    fn zone_without_schedule() {
+        let zones_config_file_path = "test_configs/zones_config.txt";
+        let config_reader =
+            ConfigFileReader::for_zone(zones_config_file_path.to_string(), "kitchen".to_string());
+
+        let schedule = config_reader.get_schedule();
+        assert!(matches!(schedule, Ok(None)));
+    }
+
+    #[test]
+    fn pid_gains_are_saved() {
+        let gains = PidGains {
+            kp: 2.5,
+            ki: 0.01,
+            kd: 30.0,
         };
+        let file_name = std::env::temp_dir()
+            .join(format!("pid_gains_config_{}.txt", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        std::fs::copy("test_configs/zones_config.txt", &file_name).unwrap();
+
+        let config_reader = ConfigFileReader::for_zone(file_name.clone(), "kitchen".to_string());
+        let saved = config_reader.save_pid_gains(&gains);
+        let kitchen = config_reader.get_config();
+        let bedroom =
+            ConfigFileReader::for_zone(file_name.clone(), "bedroom".to_string()).get_config();
+        std::fs::remove_file(&file_name).unwrap();
+
+        assert!(saved.is_ok());
+        assert!(kitchen.is_ok());
+        let kitchen = kitchen.unwrap();
+        assert!(kitchen.pid_gains == Some(gains));
+        assert!(float_cmp::approx_eq!(
+            f32,
+            kitchen.min_temperature(),
+            -5f32,
+            epsilon = 0.000001
+        ));
+        assert!(matches!(bedroom, Ok(bedroom) if bedroom.pid_gains.is_none()));
+    }
+
+    #[test]
+    fn detects_toml_file() {
+        let config_file_path = "test_configs/config.toml";
+        let config_reader = ConfigFileReader::new(config_file_path.to_string());
 
         let config = config_reader.get_config();
         assert!(config.is_ok());
+        assert!(config.unwrap().mode == Mode::Eco);
+        let schedule = config_reader.get_schedule();
+        assert!(matches!(schedule, Ok(Some(_))));
 
-        let config = config.unwrap();
-        assert!(config.is_none());
+        let zones_config_file_path = "test_configs/zones_config.toml";
+        let config_reader =
+            ConfigFileReader::for_zone(zones_config_file_path.to_string(), "kitchen".to_string());
+        let config = config_reader.get_config();
+        assert!(config.is_ok());
+        assert!(float_cmp::approx_eq!(
+            f32,
+            config.unwrap().min_temperature(),
+            -5f32,
+            epsilon = 0.000001
+        ));
+    }
+
+    #[test]
+    fn inverted_limits_are_rejected() {
+        let config_file_path = "test_configs/inverted_config.txt";
+        let config_reader = ConfigFileReader::new(config_file_path.to_string());
+
+        let config = config_reader.get_config();
+        let diagnostics = match config {
+            Err(ConfigError::Rejected(diagnostics)) => diagnostics,
+            other => panic!("expected the config to be rejected, got {other:?}"),
+        };
+        assert!(diagnostics.len() == 1);
+        assert!(diagnostics[0].field == "min_temperature");
+        assert!(diagnostics[0].location == Some(Location { line: 1, column: 1 }));
+    }
+
+    #[test]
+    fn non_finite_values_are_rejected() {
+        let config_file_path = "test_configs/non_finite_config.txt";
+        let config_reader = ConfigFileReader::new(config_file_path.to_string());
+
+        let config = config_reader.get_config();
+        let diagnostics = match config {
+            Err(ConfigError::Rejected(diagnostics)) => diagnostics,
+            other => panic!("expected the config to be rejected, got {other:?}"),
+        };
+        let fields = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.field.as_str(), diagnostic.location))
+            .collect::<Vec<_>>();
+        assert!(
+            fields
+                == vec![
+                    (
+                        "heating_deadband",
+                        Some(Location {
+                            line: 1,
+                            column: 13
+                        })
+                    ),
+                    (
+                        "cooling_deadband",
+                        Some(Location {
+                            line: 1,
+                            column: 34
+                        })
+                    ),
+                ]
+        );
+    }
+
+    #[test]
+    fn trailing_value_config_file() {
+        let config_file_path = "test_configs/trailing_value_config.txt";
+        let config_reader = ConfigFileReader::new(config_file_path.to_string());
+
+        let config = config_reader.get_config();
+        assert!(matches!(config, Err(ConfigError::Invalid(_))));
     }
 }
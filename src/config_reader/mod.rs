@@ -1,45 +1,427 @@
-pub mod file_reader;
+pub mod cache;
 pub mod db_reader;
+pub mod document;
+pub mod document_reader;
+pub mod file_reader;
+pub mod toml_reader;
+pub mod validation;
+pub mod watcher;
+
+use crate::control_strategy::pid::PidGains;
+
+pub const DEFAULT_DEADBAND: f32 = 1.0;
+pub const DEFAULT_OVERSHOOT: f32 = 1.0;
+pub const DEFAULT_ECO_OFFSET: f32 = 2.0;
+pub const DEFAULT_AWAY_OFFSET: f32 = 4.0;
+pub const DEFAULT_CONTROL_PERIOD: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// User-selected operating mode, as opposed to `SystemState` which describes
+/// what the plant is currently doing.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Mode {
+    Off,
+    HeatOnly,
+    CoolOnly,
+    #[default]
+    Auto,
+    Eco,
+    Away,
+}
+
+impl Mode {
+    pub fn allows_heating(&self) -> bool {
+        !matches!(self, Mode::Off | Mode::CoolOnly)
+    }
+
+    pub fn allows_cooling(&self) -> bool {
+        !matches!(self, Mode::Off | Mode::HeatOnly)
+    }
+}
+
+impl std::fmt::Display for Mode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Mode::Off => "off",
+            Mode::HeatOnly => "heat_only",
+            Mode::CoolOnly => "cool_only",
+            Mode::Auto => "auto",
+            Mode::Eco => "eco",
+            Mode::Away => "away",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl std::str::FromStr for Mode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "off" => Ok(Mode::Off),
+            "heat_only" | "heat" => Ok(Mode::HeatOnly),
+            "cool_only" | "cool" => Ok(Mode::CoolOnly),
+            "auto" => Ok(Mode::Auto),
+            "eco" => Ok(Mode::Eco),
+            "away" => Ok(Mode::Away),
+            _ => Err(format!("unknown mode {value}")),
+        }
+    }
+}
 
-#[derive(Clone, Copy)]
+/// Heating switches on at `setpoint - heating_deadband` and drives `heating_overshoot`
+/// degrees past that threshold; cooling mirrors this above the setpoint.
+///
+/// The minimum times protect compressors and relays from short cycling and are
+/// zero (disabled) unless configured. Eco and away modes widen both deadbands by
+/// their offset. The periods drive the control loop scheduler. When a ramp
+/// rate in degrees per minute is set, the controller moves towards a changed
+/// setpoint at that rate instead of jumping to it. PID gains are normally
+/// written back by auto-tuning rather than by hand.
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Config {
-    pub min_temperature: f32,
-    pub max_temperature: f32,
+    pub setpoint: f32,
+    pub heating_deadband: f32,
+    pub cooling_deadband: f32,
+    pub heating_overshoot: f32,
+    pub cooling_overshoot: f32,
+    pub min_run_time: std::time::Duration,
+    pub min_off_time: std::time::Duration,
+    pub min_changeover_delay: std::time::Duration,
+    pub mode: Mode,
+    pub eco_offset: f32,
+    pub away_offset: f32,
+    pub control_period: std::time::Duration,
+    pub sample_period: std::time::Duration,
+    pub setpoint_ramp_rate: Option<f32>,
+    pub pid_gains: Option<PidGains>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            setpoint: 21.0,
+            heating_deadband: DEFAULT_DEADBAND,
+            cooling_deadband: DEFAULT_DEADBAND,
+            heating_overshoot: DEFAULT_OVERSHOOT,
+            cooling_overshoot: DEFAULT_OVERSHOOT,
+            min_run_time: std::time::Duration::ZERO,
+            min_off_time: std::time::Duration::ZERO,
+            min_changeover_delay: std::time::Duration::ZERO,
+            mode: Mode::default(),
+            eco_offset: DEFAULT_ECO_OFFSET,
+            away_offset: DEFAULT_AWAY_OFFSET,
+            control_period: DEFAULT_CONTROL_PERIOD,
+            sample_period: DEFAULT_CONTROL_PERIOD,
+            setpoint_ramp_rate: None,
+            pid_gains: None,
+        }
+    }
+}
+
+impl Config {
+    /// Maps the legacy min/max pair onto a setpoint in the middle of the band.
+    pub fn from_limits(min_temperature: f32, max_temperature: f32) -> Self {
+        let setpoint = (min_temperature + max_temperature) / 2.0;
+        Config {
+            setpoint,
+            heating_deadband: setpoint - min_temperature,
+            cooling_deadband: max_temperature - setpoint,
+            ..Config::default()
+        }
+    }
+
+    pub fn min_temperature(&self) -> f32 {
+        self.setpoint - self.heating_deadband
+    }
+
+    pub fn max_temperature(&self) -> f32 {
+        self.setpoint + self.cooling_deadband
+    }
+
+    pub fn heating_target(&self) -> f32 {
+        self.min_temperature() + self.heating_overshoot
+    }
+
+    pub fn cooling_target(&self) -> f32 {
+        self.max_temperature() - self.cooling_overshoot
+    }
+
+    /// The config the strategy should act on once the mode's setback is applied.
+    pub fn with_mode_setback(&self) -> Config {
+        let setback = match self.mode {
+            Mode::Eco => self.eco_offset,
+            Mode::Away => self.away_offset,
+            _ => return *self,
+        };
+        Config {
+            heating_deadband: self.heating_deadband + setback,
+            cooling_deadband: self.cooling_deadband + setback,
+            ..*self
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// No config is stored: the file or row does not exist or is empty.
+    Missing(Option<Box<dyn std::error::Error + Send + Sync>>),
+    /// A config is stored but could not be turned into a `Config`.
+    Invalid(String),
+    /// The config was read but breaks one or more invariants.
+    Rejected(Vec<validation::Diagnostic>),
+    /// The backend holding the config could not be reached.
+    Unavailable(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Missing(_) => write!(f, "Config is missing"),
+            ConfigError::Invalid(reason) => write!(f, "Config is invalid: {reason}"),
+            ConfigError::Rejected(diagnostics) => {
+                write!(f, "Config is invalid: ")?;
+                for (index, diagnostic) in diagnostics.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{diagnostic}")?;
+                }
+                Ok(())
+            }
+            ConfigError::Unavailable(_) => write!(f, "Config backend is unavailable"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Missing(Some(source)) | ConfigError::Unavailable(source) => {
+                Some(source.as_ref())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(err: std::io::Error) -> Self {
+        if err.kind() == std::io::ErrorKind::NotFound {
+            ConfigError::Missing(Some(Box::new(err)))
+        } else {
+            ConfigError::Unavailable(Box::new(err))
+        }
+    }
 }
 
 #[mockall::automock]
 pub trait ReadConfig {
-    fn get_config(&self) -> Result<Option<Config>, Box<dyn std::error::Error>>;
+    fn get_config(&self) -> Result<Config, ConfigError>;
 }
 
-fn extract_config_from_line(line: &str) -> Option<Config> {
-    let values = line.split_whitespace().collect::<Vec<&str>>();
-    if values.len() < 2 {
-        eprintln!("Did not enter correct config values");
-        return None;
-    }
+/// Writes tuned values back to where the config is kept, leaving the rest of
+/// it untouched.
+#[mockall::automock]
+pub trait WriteConfig {
+    fn save_pid_gains(&self, gains: &PidGains) -> Result<(), ConfigError>;
+}
 
-    let min_temperature = match values[0].trim().parse::<f32>() {
-        Ok(result) => result,
-        Err(err) => {
-            eprintln!("Receiving data {line}");
-            eprintln!("Failed to convert min temp to a number. Received min temp is {err}");
-            return None;
+/// Builds a config from named values, falling back to the legacy min/max pair
+/// when no setpoint is given.
+fn config_from_fields(field: impl Fn(&str) -> Option<String>) -> Result<Config, ConfigError> {
+    let number = |name: &str| match field(name) {
+        Some(value) => match value.trim().parse::<f32>() {
+            Ok(result) => Ok(Some(result)),
+            Err(err) => Err(ConfigError::Invalid(format!(
+                "Failed to convert {name} to a number: {err}"
+            ))),
+        },
+        None => Ok(None),
+    };
+
+    let mut config = match (
+        number("setpoint")?,
+        number("min_temperature")?,
+        number("max_temperature")?,
+    ) {
+        (Some(setpoint), _, _) => Config {
+            setpoint,
+            ..Config::default()
+        },
+        (None, Some(min_temperature), Some(max_temperature)) => {
+            Config::from_limits(min_temperature, max_temperature)
+        }
+        _ => {
+            return Err(ConfigError::Invalid(
+                "Config needs either a setpoint or both min_temperature and max_temperature"
+                    .to_string(),
+            ))
         }
     };
 
-    let max_temperature = match values[1].trim().parse::<f32>() {
-        Ok(result) => result,
-        Err(err) => {
-            eprintln!("Receiving data {line}");
-            eprintln!("Failed to convert max temp to a number. Received max temp is {err}");
-            return None;
+    if let Some(value) = number("heating_deadband")? {
+        config.heating_deadband = value;
+    }
+    if let Some(value) = number("cooling_deadband")? {
+        config.cooling_deadband = value;
+    }
+    if let Some(value) = number("heating_overshoot")? {
+        config.heating_overshoot = value;
+    }
+    if let Some(value) = number("cooling_overshoot")? {
+        config.cooling_overshoot = value;
+    }
+    if let Some(value) = number("min_run_time")? {
+        config.min_run_time = duration_from_seconds("min_run_time", value)?;
+    }
+    if let Some(value) = number("min_off_time")? {
+        config.min_off_time = duration_from_seconds("min_off_time", value)?;
+    }
+    if let Some(value) = number("min_changeover_delay")? {
+        config.min_changeover_delay = duration_from_seconds("min_changeover_delay", value)?;
+    }
+    if let Some(value) = field("mode") {
+        config.mode = value
+            .trim()
+            .parse::<Mode>()
+            .map_err(|err| ConfigError::Invalid(format!("Failed to convert mode: {err}")))?;
+    }
+    if let Some(value) = number("eco_offset")? {
+        config.eco_offset = value;
+    }
+    if let Some(value) = number("away_offset")? {
+        config.away_offset = value;
+    }
+    if let Some(value) = number("control_period")? {
+        config.control_period = duration_from_seconds("control_period", value)?;
+    }
+    if let Some(value) = number("sample_period")? {
+        config.sample_period = duration_from_seconds("sample_period", value)?;
+    }
+    if let Some(value) = number("setpoint_ramp_rate")? {
+        config.setpoint_ramp_rate = Some(value);
+    }
+    config.pid_gains = match (number("pid_kp")?, number("pid_ki")?, number("pid_kd")?) {
+        (Some(kp), Some(ki), Some(kd)) => Some(PidGains { kp, ki, kd }),
+        (None, None, None) => None,
+        _ => {
+            return Err(ConfigError::Invalid(
+                "Config needs all of pid_kp, pid_ki and pid_kd or none of them".to_string(),
+            ))
         }
     };
+    Ok(config)
+}
 
-    Some(Config {
-        min_temperature,
-        max_temperature,
+fn duration_from_seconds(name: &str, seconds: f32) -> Result<std::time::Duration, ConfigError> {
+    std::time::Duration::try_from_secs_f32(seconds).map_err(|err| {
+        ConfigError::Invalid(format!(
+            "Failed to convert {name} to a duration. Received {seconds}: {err}"
+        ))
     })
 }
 
+/// Accepts either the legacy `<min> <max>` line or `key=value` pairs,
+/// e.g. `setpoint=21 heating_deadband=0.5 cooling_overshoot=1`.
+fn extract_config_from_line(line: &str) -> Result<Config, ConfigError> {
+    if line.contains('=') {
+        return extract_keyed_config_from_line(line);
+    }
+
+    let values = line.split_whitespace().collect::<Vec<&str>>();
+    if values.len() != 2 {
+        return Err(ConfigError::Invalid(format!(
+            "Expected <min> <max> but received {}",
+            line.trim()
+        )));
+    }
+
+    let min_temperature = values[0].trim().parse::<f32>().map_err(|err| {
+        ConfigError::Invalid(format!("Failed to convert min temp to a number: {err}"))
+    })?;
+    let max_temperature = values[1].trim().parse::<f32>().map_err(|err| {
+        ConfigError::Invalid(format!("Failed to convert max temp to a number: {err}"))
+    })?;
+
+    Ok(Config::from_limits(min_temperature, max_temperature))
+}
+
+/// Rewrites a config line with the given gains, turning a legacy `<min> <max>`
+/// line into the equivalent keyed one first.
+fn line_with_pid_gains(line: &str, gains: &PidGains) -> Result<String, ConfigError> {
+    let mut tokens = if line.contains('=') {
+        line.split_whitespace()
+            .filter(|token| !token.starts_with("pid_"))
+            .map(str::to_string)
+            .collect::<Vec<String>>()
+    } else {
+        let config = extract_config_from_line(line)?;
+        vec![
+            format!("min_temperature={}", config.min_temperature()),
+            format!("max_temperature={}", config.max_temperature()),
+        ]
+    };
+    tokens.push(format!("pid_kp={}", gains.kp));
+    tokens.push(format!("pid_ki={}", gains.ki));
+    tokens.push(format!("pid_kd={}", gains.kd));
+    Ok(tokens.join(" "))
+}
+
+/// Where `field` was given on a config line, the `<min> <max>` values
+/// standing for the temperature limits.
+fn locate_in_line(line: &str, line_number: usize, field: &str) -> Option<validation::Location> {
+    let mut tokens = line.split_whitespace().map(|token| {
+        let offset = token.as_ptr() as usize - line.as_ptr() as usize;
+        (token, offset)
+    });
+    let (_, offset) = if line.contains('=') {
+        tokens.find(|(token, _)| token.split_once('=').map(|(key, _)| key) == Some(field))?
+    } else {
+        match field {
+            "min_temperature" => tokens.next()?,
+            "max_temperature" => tokens.nth(1)?,
+            _ => return None,
+        }
+    };
+    Some(validation::Location {
+        line: line_number,
+        column: line[..offset].chars().count() + 1,
+    })
+}
+
+fn extract_keyed_config_from_line(line: &str) -> Result<Config, ConfigError> {
+    const KNOWN_KEYS: [&str; 19] = [
+        "setpoint",
+        "min_temperature",
+        "max_temperature",
+        "heating_deadband",
+        "cooling_deadband",
+        "heating_overshoot",
+        "cooling_overshoot",
+        "min_run_time",
+        "min_off_time",
+        "min_changeover_delay",
+        "mode",
+        "eco_offset",
+        "away_offset",
+        "control_period",
+        "sample_period",
+        "setpoint_ramp_rate",
+        "pid_kp",
+        "pid_ki",
+        "pid_kd",
+    ];
+
+    let mut fields = std::collections::HashMap::new();
+    for token in line.split_whitespace() {
+        let (key, value) = token.split_once('=').ok_or_else(|| {
+            ConfigError::Invalid(format!("Expected key=value but received {token}"))
+        })?;
+        if !KNOWN_KEYS.contains(&key) {
+            return Err(ConfigError::Invalid(format!("Unknown config key {key}")));
+        }
+        fields.insert(key, value);
+    }
+
+    config_from_fields(|key| fields.get(key).map(|value| value.to_string()))
+}
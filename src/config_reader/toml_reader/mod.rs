@@ -0,0 +1,242 @@
+use super::document::{parse_document, ConfigDocument, SECTIONS};
+use super::{Config, ConfigError, ReadConfig, WriteConfig};
+use crate::control_strategy::pid::PidGains;
+use crate::setpoint_schedule::{ReadSchedule, WeeklySchedule};
+
+/// Reads a TOML file laid out as a `ConfigDocument`. Zoned files nest the
+/// sections under the zone's name, e.g. `[kitchen.setpoint]`.
+pub struct ConfigTomlReader {
+    config_file_name: String,
+    zone: Option<String>,
+}
+
+impl ConfigTomlReader {
+    pub fn new(config_file_name: String) -> Self {
+        ConfigTomlReader {
+            config_file_name,
+            zone: None,
+        }
+    }
+
+    pub fn for_zone(config_file_name: String, zone: String) -> Self {
+        ConfigTomlReader {
+            config_file_name,
+            zone: Some(zone),
+        }
+    }
+
+    fn read_document(&self) -> Result<(String, ConfigDocument), ConfigError> {
+        let contents = std::fs::read_to_string(&self.config_file_name)?;
+        let document = document_from_str(&contents, self.zone.as_deref())?;
+        Ok((contents, document))
+    }
+}
+
+fn invalid(err: impl std::fmt::Display) -> ConfigError {
+    ConfigError::Invalid(format!("Failed to parse TOML config: {err}"))
+}
+
+pub fn document_from_str(
+    contents: &str,
+    zone: Option<&str>,
+) -> Result<ConfigDocument, ConfigError> {
+    parse_document(contents, zone, "TOML", toml::from_str, toml::from_str)
+}
+
+/// Whether `contents` is TOML with at least one config section, either at
+/// the top level or under a zone. The one-line format never parses as such.
+pub fn is_toml(contents: &str) -> bool {
+    let has_section = |table: &toml::Table| {
+        SECTIONS
+            .iter()
+            .any(|section| matches!(table.get(*section), Some(toml::Value::Table(_))))
+    };
+    match contents.parse::<toml::Table>() {
+        Ok(table) => {
+            has_section(&table)
+                || table.values().any(|value| match value {
+                    toml::Value::Table(zone) => has_section(zone),
+                    _ => false,
+                })
+        }
+        Err(_) => false,
+    }
+}
+
+impl ReadConfig for ConfigTomlReader {
+    fn get_config(&self) -> Result<Config, ConfigError> {
+        let (contents, document) = self.read_document()?;
+        document.to_validated_config(&contents, self.zone.as_deref())
+    }
+}
+
+impl ReadSchedule for ConfigTomlReader {
+    fn get_schedule(&self) -> Result<Option<WeeklySchedule>, ConfigError> {
+        self.read_document()?.1.to_schedule()
+    }
+}
+
+/// Sets the `[pid]` section, keeping comments and layout of the rest.
+impl WriteConfig for ConfigTomlReader {
+    fn save_pid_gains(&self, gains: &PidGains) -> Result<(), ConfigError> {
+        let contents = std::fs::read_to_string(&self.config_file_name)?;
+        let mut document = contents
+            .parse::<toml_edit::DocumentMut>()
+            .map_err(invalid)?;
+        let root = match &self.zone {
+            Some(zone) => document
+                .get_mut(zone)
+                .and_then(toml_edit::Item::as_table_mut)
+                .ok_or(ConfigError::Missing(None))?,
+            None => document.as_table_mut(),
+        };
+        let pid = root
+            .entry("pid")
+            .or_insert(toml_edit::table())
+            .as_table_mut()
+            .ok_or_else(|| ConfigError::Invalid("pid must be a table".to_string()))?;
+        pid["kp"] = toml_edit::value(f64::from(gains.kp));
+        pid["ki"] = toml_edit::value(f64::from(gains.ki));
+        pid["kd"] = toml_edit::value(f64::from(gains.kd));
+        std::fs::write(&self.config_file_name, document.to_string())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_reader::validation::Location;
+    use crate::config_reader::Mode;
+
+    #[test]
+    fn toml_config() {
+        let config_reader = ConfigTomlReader::new("test_configs/config.toml".to_string());
+        let expected_config = Config {
+            setpoint: 21.5,
+            heating_deadband: 0.5,
+            cooling_deadband: 1.5,
+            min_run_time: std::time::Duration::from_secs(300),
+            mode: Mode::Eco,
+            eco_offset: 1.5,
+            sample_period: std::time::Duration::from_secs(2),
+            control_period: std::time::Duration::from_secs(10),
+            setpoint_ramp_rate: Some(0.5),
+            ..Config::default()
+        };
+
+        let config = config_reader.get_config();
+        assert!(config.is_ok());
+        assert!(config.unwrap() == expected_config);
+
+        let schedule = config_reader.get_schedule();
+        assert!(schedule.is_ok());
+        let schedule = schedule.unwrap();
+        assert!(schedule.is_some());
+        let schedule = schedule.unwrap();
+        assert!(schedule.timezone() == chrono_tz::Europe::Berlin);
+        assert!(schedule.periods().len() == 7);
+    }
+
+    #[test]
+    fn toml_zone() {
+        let config_reader = ConfigTomlReader::for_zone(
+            "test_configs/zones_config.toml".to_string(),
+            "bedroom".to_string(),
+        );
+
+        let config = config_reader.get_config();
+        assert!(config.is_ok());
+        let config = config.unwrap();
+        assert!(float_cmp::approx_eq!(
+            f32,
+            config.min_temperature(),
+            16f32,
+            epsilon = 0.000001
+        ));
+        assert!(float_cmp::approx_eq!(
+            f32,
+            config.max_temperature(),
+            20f32,
+            epsilon = 0.000001
+        ));
+
+        let config_reader = ConfigTomlReader::for_zone(
+            "test_configs/zones_config.toml".to_string(),
+            "attic".to_string(),
+        );
+        assert!(matches!(
+            config_reader.get_config(),
+            Err(ConfigError::Missing(None))
+        ));
+    }
+
+    #[test]
+    fn unknown_toml_key_reports_position() {
+        let document = document_from_str("[setpoint]\nvalue = 20\nvalu = 21\n", None);
+
+        let message = match document {
+            Err(ConfigError::Invalid(message)) => message,
+            other => panic!("expected an invalid config, got {other:?}"),
+        };
+        assert!(message.contains("line 3"));
+        assert!(message.contains("valu"));
+    }
+
+    #[test]
+    fn detects_format() {
+        assert!(is_toml("[setpoint]\nvalue = 20\n"));
+        assert!(is_toml("[kitchen.setpoint]\nvalue = 20\n"));
+        assert!(!is_toml("-9 15\n"));
+        assert!(!is_toml("setpoint=20\n"));
+        assert!(!is_toml(
+            "[kitchen]\n-5 10\n\n[bedroom]\nsetpoint=18 mode=eco\n"
+        ));
+    }
+
+    #[test]
+    fn pid_gains_are_saved() {
+        let file_name = std::env::temp_dir()
+            .join(format!("pid_gains_config_{}.toml", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        std::fs::copy("test_configs/config.toml", &file_name).unwrap();
+        let gains = PidGains {
+            kp: 1.5,
+            ki: 0.25,
+            kd: 4.0,
+        };
+
+        let config_reader = ConfigTomlReader::new(file_name.clone());
+        let saved = config_reader.save_pid_gains(&gains);
+        let config = config_reader.get_config();
+        let contents = std::fs::read_to_string(&file_name).unwrap();
+        std::fs::remove_file(&file_name).unwrap();
+
+        assert!(saved.is_ok());
+        assert!(matches!(config, Ok(config) if config.pid_gains == Some(gains)));
+        assert!(contents.contains("# degrees per minute"));
+    }
+
+    #[test]
+    fn rejected_values_point_into_file() {
+        let config_reader = ConfigTomlReader::new("test_configs/rejected_config.toml".to_string());
+
+        let config = config_reader.get_config();
+        let diagnostics = match config {
+            Err(ConfigError::Rejected(diagnostics)) => diagnostics,
+            other => panic!("expected the config to be rejected, got {other:?}"),
+        };
+        let fields = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.field.as_str(), diagnostic.location))
+            .collect::<Vec<_>>();
+        assert!(
+            fields
+                == vec![
+                    ("setpoint", Some(Location { line: 4, column: 1 })),
+                    ("max_temperature", None),
+                ]
+        );
+    }
+}
@@ -0,0 +1,298 @@
+use super::{Config, ConfigError};
+
+/// Setpoints outside this range are almost certainly typos.
+pub const MIN_SETPOINT: f32 = -40.0;
+pub const MAX_SETPOINT: f32 = 60.0;
+/// Narrower bands make the plant switch on every bit of sensor noise.
+pub const MIN_BAND_WIDTH: f32 = 0.5;
+
+/// Where in a config file a value was found, both 1-based.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    /// The location of byte `offset` in `contents`.
+    pub fn of_offset(contents: &str, offset: usize) -> Self {
+        let before = &contents[..offset.min(contents.len())];
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        Location {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+/// A problem with one config field. `location` is only known for files.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Diagnostic {
+    pub field: String,
+    pub message: String,
+    pub location: Option<Location>,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)?;
+        if let Some(location) = self.location {
+            write!(f, " (line {}, column {})", location.line, location.column)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the invariants every backend's config must meet, returning
+/// `ConfigError::Rejected` with one diagnostic per problem. `locate` maps a
+/// field name to where the backend found it, if it knows.
+pub fn validate(
+    config: Config,
+    locate: impl Fn(&str) -> Option<Location>,
+) -> Result<Config, ConfigError> {
+    let mut diagnostics = Vec::new();
+    let mut check = |field: &str, valid: bool, message: String| {
+        if !valid {
+            diagnostics.push(Diagnostic {
+                field: field.to_string(),
+                message,
+                location: locate(field),
+            });
+        }
+    };
+
+    let mut numbers = vec![
+        ("setpoint", config.setpoint),
+        ("heating_deadband", config.heating_deadband),
+        ("cooling_deadband", config.cooling_deadband),
+        ("heating_overshoot", config.heating_overshoot),
+        ("cooling_overshoot", config.cooling_overshoot),
+        ("eco_offset", config.eco_offset),
+        ("away_offset", config.away_offset),
+    ];
+    numbers.extend(
+        config
+            .setpoint_ramp_rate
+            .map(|rate| ("setpoint_ramp_rate", rate)),
+    );
+    if let Some(gains) = config.pid_gains {
+        numbers.extend([
+            ("pid_kp", gains.kp),
+            ("pid_ki", gains.ki),
+            ("pid_kd", gains.kd),
+        ]);
+    }
+    let mut finite = true;
+    for (field, value) in numbers {
+        finite &= value.is_finite();
+        check(
+            field,
+            value.is_finite(),
+            format!("{value} is not a finite number"),
+        );
+        if !matches!(field, "setpoint" | "heating_deadband" | "cooling_deadband")
+            && value.is_finite()
+        {
+            check(field, value >= 0.0, format!("{value} must not be negative"));
+        }
+    }
+    if !finite {
+        return Err(ConfigError::Rejected(diagnostics));
+    }
+
+    check(
+        "setpoint",
+        (MIN_SETPOINT..=MAX_SETPOINT).contains(&config.setpoint),
+        format!(
+            "{} is outside {MIN_SETPOINT} to {MAX_SETPOINT}",
+            config.setpoint
+        ),
+    );
+    let (min_temperature, max_temperature) = (config.min_temperature(), config.max_temperature());
+    if min_temperature > max_temperature {
+        check(
+            "min_temperature",
+            false,
+            format!("{min_temperature} is above max_temperature {max_temperature}"),
+        );
+    } else if config.heating_deadband < 0.0 || config.cooling_deadband < 0.0 {
+        for (field, deadband) in [
+            ("heating_deadband", config.heating_deadband),
+            ("cooling_deadband", config.cooling_deadband),
+        ] {
+            check(
+                field,
+                deadband >= 0.0,
+                format!("{deadband} puts the setpoint outside the band"),
+            );
+        }
+    } else {
+        check(
+            "max_temperature",
+            max_temperature - min_temperature >= MIN_BAND_WIDTH,
+            format!(
+                "{max_temperature} leaves less than {MIN_BAND_WIDTH} degrees above min_temperature {min_temperature}"
+            ),
+        );
+    }
+    if let Some(rate) = config.setpoint_ramp_rate {
+        check(
+            "setpoint_ramp_rate",
+            rate > 0.0,
+            "must be above zero to ever reach the setpoint".to_string(),
+        );
+    }
+    check(
+        "control_period",
+        !config.control_period.is_zero(),
+        "must be above zero".to_string(),
+    );
+    check(
+        "sample_period",
+        !config.sample_period.is_zero(),
+        "must be above zero".to_string(),
+    );
+
+    match diagnostics.is_empty() {
+        true => Ok(config),
+        false => Err(ConfigError::Rejected(diagnostics)),
+    }
+}
+
+/// Why `value` cannot be used as a setpoint or band limit, if it cannot.
+pub fn temperature_problem(value: f32) -> Option<String> {
+    if !value.is_finite() {
+        Some(format!("{value} is not a finite number"))
+    } else if !(MIN_SETPOINT..=MAX_SETPOINT).contains(&value) {
+        Some(format!(
+            "{value} is outside {MIN_SETPOINT} to {MAX_SETPOINT}"
+        ))
+    } else {
+        None
+    }
+}
+
+/// Checks a band given by its limits, such as a vacation's, against the same
+/// invariants as the config's band.
+pub fn validate_band(min_temperature: f32, max_temperature: f32) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut check = |field: &str, problem: Option<String>| {
+        if let Some(message) = problem {
+            diagnostics.push(Diagnostic {
+                field: field.to_string(),
+                message,
+                location: None,
+            });
+        }
+    };
+    let min_problem = temperature_problem(min_temperature);
+    let max_problem = temperature_problem(max_temperature);
+    let limits_valid = min_problem.is_none() && max_problem.is_none();
+    check("min_temperature", min_problem);
+    check("max_temperature", max_problem);
+    if !limits_valid {
+        return diagnostics;
+    }
+    if min_temperature > max_temperature {
+        check(
+            "min_temperature",
+            Some(format!(
+                "{min_temperature} is above max_temperature {max_temperature}"
+            )),
+        );
+    } else if max_temperature - min_temperature < MIN_BAND_WIDTH {
+        check(
+            "max_temperature",
+            Some(format!(
+                "{max_temperature} leaves less than {MIN_BAND_WIDTH} degrees above min_temperature {min_temperature}"
+            )),
+        );
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejected_fields(config: Config) -> Vec<String> {
+        match validate(config, |_| None) {
+            Err(ConfigError::Rejected(diagnostics)) => diagnostics
+                .into_iter()
+                .map(|diagnostic| diagnostic.field)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn accepts_default_config() {
+        assert!(validate(Config::default(), |_| None).is_ok());
+        assert!(validate(Config::from_limits(-9.0, 15.0), |_| None).is_ok());
+    }
+
+    #[test]
+    fn rejects_inverted_and_narrow_bands() {
+        assert!(rejected_fields(Config::from_limits(22.0, 18.0)) == vec!["min_temperature"]);
+        assert!(rejected_fields(Config::from_limits(20.0, 20.2)) == vec!["max_temperature"]);
+    }
+
+    #[test]
+    fn rejects_non_finite_values() {
+        let config = Config {
+            setpoint: f32::NAN,
+            cooling_overshoot: f32::INFINITY,
+            ..Config::default()
+        };
+
+        assert!(rejected_fields(config) == vec!["setpoint", "cooling_overshoot"]);
+    }
+
+    #[test]
+    fn reports_every_problem_with_its_location() {
+        let config = Config {
+            setpoint: 120.0,
+            heating_deadband: -1.0,
+            cooling_deadband: 2.0,
+            control_period: std::time::Duration::ZERO,
+            ..Config::default()
+        };
+
+        let diagnostics = match validate(config, |field| {
+            (field == "setpoint").then_some(Location { line: 2, column: 7 })
+        }) {
+            Err(ConfigError::Rejected(diagnostics)) => diagnostics,
+            other => panic!("expected the config to be rejected, got {other:?}"),
+        };
+
+        assert!(diagnostics.len() == 3);
+        assert!(
+            diagnostics[0].to_string() == "setpoint: 120 is outside -40 to 60 (line 2, column 7)"
+        );
+        assert!(diagnostics[1].field == "heating_deadband");
+        assert!(diagnostics[2].field == "control_period");
+    }
+
+    #[test]
+    fn checks_bands_by_their_limits() {
+        let fields = |min_temperature, max_temperature| {
+            validate_band(min_temperature, max_temperature)
+                .into_iter()
+                .map(|diagnostic| diagnostic.field)
+                .collect::<Vec<_>>()
+        };
+
+        assert!(fields(12.0, 28.0).is_empty());
+        assert!(fields(28.0, 12.0) == vec!["min_temperature"]);
+        assert!(fields(12.0, 12.2) == vec!["max_temperature"]);
+        assert!(fields(f32::NAN, 80.0) == vec!["min_temperature", "max_temperature"]);
+    }
+
+    #[test]
+    fn offsets_become_lines_and_columns() {
+        let contents = "[setpoint]\nvalue = 20\n";
+
+        assert!(Location::of_offset(contents, 0) == Location { line: 1, column: 1 });
+        assert!(Location::of_offset(contents, 19) == Location { line: 2, column: 9 });
+    }
+}
@@ -0,0 +1,204 @@
+use super::{Config, ConfigError, ReadConfig};
+use crate::clock::{Clock, SystemClock};
+use crate::temperature_controller::events::ControllerEvent;
+
+/// How long the file has to stay untouched before it is reloaded, so editors
+/// that write a temporary file and rename it trigger a single reload.
+pub const DEFAULT_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(500);
+
+pub type ReloadSubscriber = Box<dyn FnMut(&ControllerEvent) + Send>;
+
+struct WatchState {
+    clock: std::sync::Arc<dyn Clock>,
+    changed_at: Option<std::time::Instant>,
+    last_good: Option<Config>,
+}
+
+/// Serves the config read by `reader` from memory and only reads it again
+/// once filesystem events show the file changed. A reload that fails keeps
+/// the last good config in use and is reported to subscribers as
+/// `ConfigRejected`; one that succeeds is reported as `ConfigChanged`.
+pub struct WatchingConfigReader {
+    reader: Box<dyn ReadConfig + Send>,
+    state: std::sync::Arc<std::sync::Mutex<WatchState>>,
+    debounce: std::time::Duration,
+    subscribers: std::sync::Mutex<Vec<ReloadSubscriber>>,
+    _watcher: notify::RecommendedWatcher,
+}
+
+impl WatchingConfigReader {
+    /// Watches the directory holding `config_file_name` rather than the file
+    /// itself, so the watch survives the file being replaced.
+    pub fn build(
+        config_file_name: String,
+        reader: Box<dyn ReadConfig + Send>,
+    ) -> Result<Self, ConfigError> {
+        use notify::Watcher;
+
+        let path = std::path::Path::new(&config_file_name);
+        let file_name = path.file_name().map(|name| name.to_os_string());
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => std::path::PathBuf::from("."),
+        };
+        let state = std::sync::Arc::new(std::sync::Mutex::new(WatchState {
+            clock: std::sync::Arc::new(SystemClock::default()),
+            changed_at: None,
+            last_good: None,
+        }));
+
+        let watched_state = state.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let touches_file = match event {
+                    Ok(event) => event
+                        .paths
+                        .iter()
+                        .any(|changed| changed.file_name() == file_name.as_deref()),
+                    // Events may have been lost, so assume the file changed.
+                    Err(_) => true,
+                };
+                if touches_file {
+                    let mut state = watched_state.lock().unwrap();
+                    state.changed_at = Some(state.clock.now());
+                }
+            })
+            .map_err(|err| ConfigError::Unavailable(Box::new(err)))?;
+        watcher
+            .watch(&directory, notify::RecursiveMode::NonRecursive)
+            .map_err(|err| ConfigError::Unavailable(Box::new(err)))?;
+
+        Ok(WatchingConfigReader {
+            reader,
+            state,
+            debounce: DEFAULT_DEBOUNCE,
+            subscribers: std::sync::Mutex::new(Vec::new()),
+            _watcher: watcher,
+        })
+    }
+
+    pub fn with_clock(self, clock: std::sync::Arc<dyn Clock>) -> Self {
+        self.state.lock().unwrap().clock = clock;
+        self
+    }
+
+    pub fn with_debounce(mut self, debounce: std::time::Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    pub fn subscribe(&self, subscriber: ReloadSubscriber) {
+        self.subscribers.lock().unwrap().push(subscriber);
+    }
+
+    fn publish(&self, event: &ControllerEvent) {
+        for subscriber in self.subscribers.lock().unwrap().iter_mut() {
+            subscriber(event);
+        }
+    }
+
+    /// Whether a change has been seen that is not yet reloaded.
+    pub fn has_pending_change(&self) -> bool {
+        self.state.lock().unwrap().changed_at.is_some()
+    }
+}
+
+impl ReadConfig for WatchingConfigReader {
+    fn get_config(&self) -> Result<Config, ConfigError> {
+        let mut state = self.state.lock().unwrap();
+        let previous = match (state.last_good, state.changed_at) {
+            (None, _) => None,
+            (Some(config), None) => return Ok(config),
+            (Some(config), Some(changed_at)) => {
+                if state.clock.now().duration_since(changed_at) < self.debounce {
+                    return Ok(config);
+                }
+                Some(config)
+            }
+        };
+        state.changed_at = None;
+        drop(state);
+
+        let current = match (self.reader.get_config(), previous) {
+            (Ok(current), _) => current,
+            (Err(err), None) => return Err(err),
+            (Err(err), Some(previous)) => {
+                self.publish(&ControllerEvent::ConfigRejected { error: &err });
+                return Ok(previous);
+            }
+        };
+        self.state.lock().unwrap().last_good = Some(current);
+        if previous.is_some() && previous != Some(current) {
+            self.publish(&ControllerEvent::ConfigChanged {
+                previous: previous.as_ref(),
+                current: &current,
+            });
+        }
+        Ok(current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::config_reader::file_reader::ConfigFileReader;
+
+    fn wait_for_change(reader: &WatchingConfigReader) {
+        for _ in 0..200 {
+            if reader.has_pending_change() {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        panic!("no change to the config file was seen");
+    }
+
+    #[test]
+    fn reloads_after_change_and_keeps_last_good() {
+        let directory = std::env::temp_dir().join(format!("config_watch_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let file_name = directory.join("config.txt").to_string_lossy().into_owned();
+        std::fs::write(&file_name, "setpoint=20\n").unwrap();
+
+        let clock = std::sync::Arc::new(ManualClock::default());
+        let reader = WatchingConfigReader::build(
+            file_name.clone(),
+            Box::new(ConfigFileReader::new(file_name.clone())),
+        );
+        assert!(reader.is_ok());
+        let reader = reader
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_debounce(std::time::Duration::from_secs(1));
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen_events = events.clone();
+        reader.subscribe(Box::new(move |event| {
+            let name = match event {
+                ControllerEvent::ConfigChanged { current, .. } => {
+                    format!("changed to {}", current.setpoint)
+                }
+                ControllerEvent::ConfigRejected { .. } => "rejected".to_string(),
+                _ => "other".to_string(),
+            };
+            seen_events.lock().unwrap().push(name);
+        }));
+
+        assert!(matches!(reader.get_config(), Ok(config) if config.setpoint == 20.0));
+
+        std::fs::write(&file_name, "setpoint=22\n").unwrap();
+        wait_for_change(&reader);
+        assert!(matches!(reader.get_config(), Ok(config) if config.setpoint == 20.0));
+        clock.advance(std::time::Duration::from_secs(1));
+        assert!(matches!(reader.get_config(), Ok(config) if config.setpoint == 22.0));
+
+        std::fs::write(&file_name, "setpoint=hot\n").unwrap();
+        wait_for_change(&reader);
+        clock.advance(std::time::Duration::from_secs(1));
+        let config = reader.get_config();
+
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(matches!(config, Ok(config) if config.setpoint == 22.0));
+        assert!(*events.lock().unwrap() == vec!["changed to 22", "rejected"]);
+    }
+}
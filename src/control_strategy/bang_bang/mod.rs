@@ -0,0 +1,35 @@
+use crate::config_reader::Config;
+use crate::control_strategy::{ActuatorCommand, ControlDecision, ControlStrategy};
+use crate::temperature_controller::SystemState;
+
+/// Switches on when a band edge is reached and drives one degree back inside the band.
+#[derive(Default)]
+pub struct BangBangStrategy {}
+
+impl ControlStrategy for BangBangStrategy {
+    fn decide(
+        &mut self,
+        current_temperature: f32,
+        config: &Config,
+        current_state: SystemState,
+        _elapsed: std::time::Duration,
+    ) -> ControlDecision {
+        match current_state {
+            SystemState::Idle if current_temperature <= config.min_temperature() => {
+                ControlDecision::new(ActuatorCommand::Hold, SystemState::Heating)
+            }
+            SystemState::Idle if current_temperature >= config.max_temperature() => {
+                ControlDecision::new(ActuatorCommand::Hold, SystemState::Cooling)
+            }
+            SystemState::Idle => ControlDecision::new(ActuatorCommand::Hold, SystemState::Idle),
+            SystemState::Cooling => ControlDecision::new(
+                ActuatorCommand::Lower(config.cooling_target()),
+                SystemState::Idle,
+            ),
+            SystemState::Heating => ControlDecision::new(
+                ActuatorCommand::Raise(config.heating_target()),
+                SystemState::Idle,
+            ),
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use crate::config_reader::Config;
+use crate::control_strategy::{ActuatorCommand, ControlDecision, ControlStrategy};
+use crate::temperature_controller::SystemState;
+
+/// Starts acting as soon as a band edge is crossed and keeps acting until the
+/// temperature is `hysteresis` degrees back inside the band.
+pub struct HysteresisStrategy {
+    hysteresis: f32,
+}
+
+impl HysteresisStrategy {
+    pub fn new(hysteresis: f32) -> Self {
+        HysteresisStrategy { hysteresis }
+    }
+}
+
+impl Default for HysteresisStrategy {
+    fn default() -> Self {
+        HysteresisStrategy::new(1.0)
+    }
+}
+
+impl ControlStrategy for HysteresisStrategy {
+    fn decide(
+        &mut self,
+        current_temperature: f32,
+        config: &Config,
+        current_state: SystemState,
+        _elapsed: std::time::Duration,
+    ) -> ControlDecision {
+        let heating_target = config.min_temperature() + self.hysteresis;
+        let cooling_target = config.max_temperature() - self.hysteresis;
+        let heat =
+            ControlDecision::new(ActuatorCommand::Raise(heating_target), SystemState::Heating);
+        let cool =
+            ControlDecision::new(ActuatorCommand::Lower(cooling_target), SystemState::Cooling);
+        let idle = ControlDecision::new(ActuatorCommand::Hold, SystemState::Idle);
+
+        match current_state {
+            SystemState::Idle if current_temperature <= config.min_temperature() => heat,
+            SystemState::Idle if current_temperature >= config.max_temperature() => cool,
+            SystemState::Idle => idle,
+            SystemState::Heating if current_temperature < heating_target => heat,
+            SystemState::Heating => idle,
+            SystemState::Cooling if current_temperature > cooling_target => cool,
+            SystemState::Cooling => idle,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config::from_limits(18f32, 24f32)
+    }
+
+    #[test]
+    fn starts_heating_at_lower_edge() {
+        let mut strategy = HysteresisStrategy::new(2.0);
+
+        let decision = strategy.decide(
+            18.0,
+            &config(),
+            SystemState::Idle,
+            std::time::Duration::ZERO,
+        );
+
+        assert!(
+            decision == ControlDecision::new(ActuatorCommand::Raise(20.0), SystemState::Heating)
+        );
+    }
+
+    #[test]
+    fn keeps_heating_inside_hysteresis() {
+        let mut strategy = HysteresisStrategy::new(2.0);
+
+        let decision = strategy.decide(
+            19.0,
+            &config(),
+            SystemState::Heating,
+            std::time::Duration::ZERO,
+        );
+
+        assert!(
+            decision == ControlDecision::new(ActuatorCommand::Raise(20.0), SystemState::Heating)
+        );
+    }
+
+    #[test]
+    fn stops_cooling_past_hysteresis() {
+        let mut strategy = HysteresisStrategy::new(2.0);
+
+        let decision = strategy.decide(
+            21.5,
+            &config(),
+            SystemState::Cooling,
+            std::time::Duration::ZERO,
+        );
+
+        assert!(decision == ControlDecision::new(ActuatorCommand::Hold, SystemState::Idle));
+    }
+}
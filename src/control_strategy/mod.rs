@@ -0,0 +1,54 @@
+use crate::config_reader::Config;
+use crate::temperature_controller::SystemState;
+
+pub mod bang_bang;
+pub mod hysteresis;
+pub mod pid;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ActuatorCommand {
+    Hold,
+    Raise(f32),
+    Lower(f32),
+}
+
+impl ActuatorCommand {
+    /// Whether issuing this command keeps the plant doing what `state` describes.
+    pub fn is_compatible_with(&self, state: SystemState) -> bool {
+        matches!(
+            (self, state),
+            (ActuatorCommand::Hold, _)
+                | (ActuatorCommand::Raise(_), SystemState::Heating)
+                | (ActuatorCommand::Lower(_), SystemState::Cooling)
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ControlDecision {
+    pub command: ActuatorCommand,
+    pub next_state: SystemState,
+}
+
+impl ControlDecision {
+    pub fn new(command: ActuatorCommand, next_state: SystemState) -> Self {
+        ControlDecision {
+            command,
+            next_state,
+        }
+    }
+}
+
+/// Decides what the actuator should do next from the latest reading.
+///
+/// `elapsed` is the time since the previous decision, zero on the first one.
+#[mockall::automock]
+pub trait ControlStrategy {
+    fn decide(
+        &mut self,
+        current_temperature: f32,
+        config: &Config,
+        current_state: SystemState,
+        elapsed: std::time::Duration,
+    ) -> ControlDecision;
+}
@@ -0,0 +1,280 @@
+use crate::config_reader::Config;
+use crate::control_strategy::pid::PidGains;
+use crate::control_strategy::{ActuatorCommand, ControlDecision, ControlStrategy};
+use crate::temperature_controller::SystemState;
+
+pub const DEFAULT_RELAY_AMPLITUDE: f32 = 2.0;
+pub const DEFAULT_HYSTERESIS: f32 = 0.2;
+pub const DEFAULT_CYCLES: usize = 3;
+pub const DEFAULT_MAX_DURATION: std::time::Duration = std::time::Duration::from_secs(6 * 3600);
+
+/// `relay_amplitude` is the swing of the relay in the same degrees the PID
+/// output uses; `hysteresis` keeps sensor noise from flipping it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AutoTuneSettings {
+    pub relay_amplitude: f32,
+    pub hysteresis: f32,
+    /// Oscillations averaged once the first, transient one is discarded.
+    pub cycles: usize,
+    pub max_duration: std::time::Duration,
+}
+
+impl Default for AutoTuneSettings {
+    fn default() -> Self {
+        AutoTuneSettings {
+            relay_amplitude: DEFAULT_RELAY_AMPLITUDE,
+            hysteresis: DEFAULT_HYSTERESIS,
+            cycles: DEFAULT_CYCLES,
+            max_duration: DEFAULT_MAX_DURATION,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum AutoTuneStatus {
+    Running,
+    Completed(AutoTuneResult),
+    Failed(String),
+}
+
+/// What the oscillations showed and the Ziegler–Nichols gains derived from it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AutoTuneResult {
+    pub ultimate_gain: f32,
+    pub ultimate_period: std::time::Duration,
+    pub gains: PidGains,
+}
+
+impl AutoTuneResult {
+    fn from_oscillation(settings: &AutoTuneSettings, amplitude: f32, period: f32) -> Self {
+        // Describing function of a relay with hysteresis.
+        let effective_amplitude = (amplitude.powi(2) - settings.hysteresis.powi(2))
+            .max(0.0)
+            .sqrt()
+            .max(f32::EPSILON);
+        let ultimate_gain =
+            4.0 * settings.relay_amplitude / (std::f32::consts::PI * effective_amplitude);
+        AutoTuneResult {
+            ultimate_gain,
+            ultimate_period: std::time::Duration::from_secs_f32(period),
+            gains: PidGains {
+                kp: 0.6 * ultimate_gain,
+                ki: 1.2 * ultimate_gain / period,
+                kd: 0.075 * ultimate_gain * period,
+            },
+        }
+    }
+}
+
+/// Relay (Åström–Hägglund) auto-tuning: switches the actuator fully one way
+/// and then the other around the setpoint and measures the limit cycle that
+/// builds up. One cycle runs from one switch to heating to the next.
+pub struct RelayAutoTune {
+    settings: AutoTuneSettings,
+    status: AutoTuneStatus,
+    heating: Option<bool>,
+    running_time: f32,
+    cycle_start: Option<f32>,
+    cycle_min: f32,
+    cycle_max: f32,
+    /// Amplitude and period of every complete cycle.
+    cycles: Vec<(f32, f32)>,
+}
+
+impl RelayAutoTune {
+    pub fn new(settings: AutoTuneSettings) -> Self {
+        RelayAutoTune {
+            settings,
+            status: AutoTuneStatus::Running,
+            heating: None,
+            running_time: 0.0,
+            cycle_start: None,
+            cycle_min: f32::INFINITY,
+            cycle_max: f32::NEG_INFINITY,
+            cycles: Vec::new(),
+        }
+    }
+
+    pub fn status(&self) -> &AutoTuneStatus {
+        &self.status
+    }
+
+    fn record_cycle(&mut self) {
+        if let Some(start) = self.cycle_start {
+            let amplitude = (self.cycle_max - self.cycle_min) / 2.0;
+            self.cycles.push((amplitude, self.running_time - start));
+        }
+        self.cycle_start = Some(self.running_time);
+        self.cycle_min = f32::INFINITY;
+        self.cycle_max = f32::NEG_INFINITY;
+
+        if self.cycles.len() <= self.settings.cycles {
+            return;
+        }
+        let measured = &self.cycles[1..];
+        let count = measured.len() as f32;
+        let amplitude = measured.iter().map(|(amplitude, _)| amplitude).sum::<f32>() / count;
+        let period = measured.iter().map(|(_, period)| period).sum::<f32>() / count;
+        self.status = if amplitude > self.settings.hysteresis && period > 0.0 {
+            AutoTuneStatus::Completed(AutoTuneResult::from_oscillation(
+                &self.settings,
+                amplitude,
+                period,
+            ))
+        } else {
+            AutoTuneStatus::Failed(format!(
+                "Oscillation of {amplitude} degrees is too small to measure"
+            ))
+        };
+    }
+}
+
+impl ControlStrategy for RelayAutoTune {
+    fn decide(
+        &mut self,
+        current_temperature: f32,
+        config: &Config,
+        _current_state: SystemState,
+        elapsed: std::time::Duration,
+    ) -> ControlDecision {
+        if self.status != AutoTuneStatus::Running {
+            return ControlDecision::new(ActuatorCommand::Hold, SystemState::Idle);
+        }
+        self.running_time += elapsed.as_secs_f32();
+        if self.running_time > self.settings.max_duration.as_secs_f32() {
+            self.status = AutoTuneStatus::Failed(format!(
+                "No steady oscillation within {:?}",
+                self.settings.max_duration
+            ));
+            return ControlDecision::new(ActuatorCommand::Hold, SystemState::Idle);
+        }
+        self.cycle_min = self.cycle_min.min(current_temperature);
+        self.cycle_max = self.cycle_max.max(current_temperature);
+
+        let heating = match self.heating {
+            None => current_temperature < config.setpoint,
+            Some(true) => current_temperature < config.setpoint + self.settings.hysteresis,
+            Some(false) => current_temperature <= config.setpoint - self.settings.hysteresis,
+        };
+        if heating && self.heating == Some(false) {
+            self.record_cycle();
+        }
+        self.heating = Some(heating);
+
+        if heating {
+            ControlDecision::new(
+                ActuatorCommand::Raise(config.setpoint + self.settings.relay_amplitude),
+                SystemState::Heating,
+            )
+        } else {
+            ControlDecision::new(
+                ActuatorCommand::Lower(config.setpoint - self.settings.relay_amplitude),
+                SystemState::Cooling,
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ONE_MINUTE: std::time::Duration = std::time::Duration::from_secs(60);
+
+    /// A triangle wave between 19 and 21 with a 20 minute period.
+    fn triangle(minute: u32) -> f32 {
+        let phase = (minute % 20) as f32;
+        if phase < 10.0 {
+            21.0 - phase / 5.0
+        } else {
+            19.0 + (phase - 10.0) / 5.0
+        }
+    }
+
+    #[test]
+    fn measures_oscillation() {
+        let settings = AutoTuneSettings {
+            relay_amplitude: 2.0,
+            hysteresis: 0.0,
+            cycles: 2,
+            ..AutoTuneSettings::default()
+        };
+        let mut autotune = RelayAutoTune::new(settings);
+        let config = Config {
+            setpoint: 20.0,
+            ..Config::default()
+        };
+
+        let mut minute = 0;
+        while *autotune.status() == AutoTuneStatus::Running && minute < 200 {
+            autotune.decide(triangle(minute), &config, SystemState::Idle, ONE_MINUTE);
+            minute += 1;
+        }
+
+        let result = match autotune.status() {
+            AutoTuneStatus::Completed(result) => *result,
+            status => panic!("auto-tune did not complete: {status:?}"),
+        };
+        let expected_gain = 4.0 * 2.0 / std::f32::consts::PI;
+        assert!(result.ultimate_period == std::time::Duration::from_secs(20 * 60));
+        assert!(float_cmp::approx_eq!(
+            f32,
+            result.ultimate_gain,
+            expected_gain,
+            epsilon = 0.0001
+        ));
+        assert!(float_cmp::approx_eq!(
+            f32,
+            result.gains.kp,
+            0.6 * expected_gain,
+            epsilon = 0.0001
+        ));
+        assert!(float_cmp::approx_eq!(
+            f32,
+            result.gains.ki,
+            1.2 * expected_gain / 1200.0,
+            epsilon = 0.000001
+        ));
+    }
+
+    #[test]
+    fn relay_switches_with_hysteresis() {
+        let mut autotune = RelayAutoTune::new(AutoTuneSettings {
+            relay_amplitude: 1.0,
+            hysteresis: 0.5,
+            ..AutoTuneSettings::default()
+        });
+        let config = Config {
+            setpoint: 20.0,
+            ..Config::default()
+        };
+
+        let decision = autotune.decide(19.0, &config, SystemState::Idle, ONE_MINUTE);
+        assert!(
+            decision == ControlDecision::new(ActuatorCommand::Raise(21.0), SystemState::Heating)
+        );
+        let decision = autotune.decide(20.4, &config, SystemState::Heating, ONE_MINUTE);
+        assert!(decision.next_state == SystemState::Heating);
+        let decision = autotune.decide(20.5, &config, SystemState::Heating, ONE_MINUTE);
+        assert!(
+            decision == ControlDecision::new(ActuatorCommand::Lower(19.0), SystemState::Cooling)
+        );
+        let decision = autotune.decide(19.6, &config, SystemState::Cooling, ONE_MINUTE);
+        assert!(decision.next_state == SystemState::Cooling);
+    }
+
+    #[test]
+    fn gives_up_without_oscillation() {
+        let mut autotune = RelayAutoTune::new(AutoTuneSettings {
+            max_duration: std::time::Duration::from_secs(600),
+            ..AutoTuneSettings::default()
+        });
+        let config = Config::default();
+
+        for _ in 0..11 {
+            autotune.decide(10.0, &config, SystemState::Heating, ONE_MINUTE);
+        }
+
+        assert!(matches!(autotune.status(), AutoTuneStatus::Failed(_)));
+    }
+}
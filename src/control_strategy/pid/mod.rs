@@ -0,0 +1,419 @@
+use crate::config_reader::validation::Diagnostic;
+use crate::config_reader::{Config, ConfigError};
+use crate::control_strategy::{ActuatorCommand, ControlDecision, ControlStrategy};
+use crate::temperature_controller::SystemState;
+
+pub mod autotune;
+
+/// The tunable part of `PidParameters`, as found by auto-tuning and kept in
+/// the config.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PidGains {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PidParameters {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+    pub output_min: f32,
+    pub output_max: f32,
+    /// Corrections no larger than this keep the plant idle, so sensor noise
+    /// around the setpoint does not flip between heating and cooling.
+    pub deadband: f32,
+}
+
+impl PidParameters {
+    /// Checks the parameters can drive a loop: finite values, an output
+    /// range that is not inverted and a deadband that is not negative.
+    pub fn validate(self) -> Result<Self, ConfigError> {
+        let mut diagnostics = Vec::new();
+        let mut check = |field: &str, valid: bool, message: String| {
+            if !valid {
+                diagnostics.push(Diagnostic {
+                    field: field.to_string(),
+                    message,
+                    location: None,
+                });
+            }
+        };
+        let mut finite = true;
+        for (field, value) in [
+            ("kp", self.kp),
+            ("ki", self.ki),
+            ("kd", self.kd),
+            ("output_min", self.output_min),
+            ("output_max", self.output_max),
+            ("deadband", self.deadband),
+        ] {
+            finite &= value.is_finite();
+            check(
+                field,
+                value.is_finite(),
+                format!("{value} is not a finite number"),
+            );
+        }
+        if !finite {
+            return Err(ConfigError::Rejected(diagnostics));
+        }
+        check(
+            "output_min",
+            self.output_min < self.output_max,
+            format!(
+                "{} is not below output_max {}",
+                self.output_min, self.output_max
+            ),
+        );
+        check(
+            "deadband",
+            self.deadband >= 0.0,
+            format!("{} must not be negative", self.deadband),
+        );
+        match diagnostics.is_empty() {
+            true => Ok(self),
+            false => Err(ConfigError::Rejected(diagnostics)),
+        }
+    }
+
+    pub fn gains(&self) -> PidGains {
+        PidGains {
+            kp: self.kp,
+            ki: self.ki,
+            kd: self.kd,
+        }
+    }
+
+    pub fn with_gains(self, gains: PidGains) -> Self {
+        PidParameters {
+            kp: gains.kp,
+            ki: gains.ki,
+            kd: gains.kd,
+            ..self
+        }
+    }
+}
+
+impl Default for PidParameters {
+    fn default() -> Self {
+        PidParameters {
+            kp: 0.5,
+            ki: 0.05,
+            kd: 0.1,
+            output_min: -5.0,
+            output_max: 5.0,
+            deadband: 0.2,
+        }
+    }
+}
+
+/// PID loop producing a temperature correction in degrees.
+///
+/// The derivative term is computed on the measurement rather than on the error,
+/// so a setpoint change does not produce a derivative kick. The integral term only
+/// accumulates while the output is not saturated in the direction of the error.
+pub struct PidController {
+    parameters: PidParameters,
+    integral: f32,
+    last_measurement: Option<f32>,
+}
+
+impl PidController {
+    pub fn new(parameters: PidParameters) -> Result<Self, ConfigError> {
+        Ok(PidController {
+            parameters: parameters.validate()?,
+            integral: 0.0,
+            last_measurement: None,
+        })
+    }
+
+    pub fn parameters(&self) -> PidParameters {
+        self.parameters
+    }
+
+    /// Swaps in new gains without resetting the accumulated state.
+    pub fn set_gains(&mut self, gains: PidGains) {
+        self.parameters = self.parameters.with_gains(gains);
+    }
+
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.last_measurement = None;
+    }
+
+    pub fn update(&mut self, setpoint: f32, measurement: f32, elapsed: std::time::Duration) -> f32 {
+        let error = setpoint - measurement;
+        let dt = elapsed.as_secs_f32();
+
+        let derivative = match self.last_measurement {
+            Some(last) if dt > 0.0 => -(measurement - last) / dt,
+            _ => 0.0,
+        };
+        self.last_measurement = Some(measurement);
+
+        let candidate_integral = self.integral + error * dt;
+        let unclamped = self.parameters.kp * error
+            + self.parameters.ki * candidate_integral
+            + self.parameters.kd * derivative;
+
+        let saturated_high = unclamped > self.parameters.output_max && error > 0.0;
+        let saturated_low = unclamped < self.parameters.output_min && error < 0.0;
+        if !saturated_high && !saturated_low {
+            self.integral = candidate_integral;
+        }
+
+        let output = self.parameters.kp * error
+            + self.parameters.ki * self.integral
+            + self.parameters.kd * derivative;
+        output.clamp(self.parameters.output_min, self.parameters.output_max)
+    }
+}
+
+/// Drives towards the configured setpoint by the PID correction, using the
+/// config's gains when it has any.
+pub struct PidStrategy {
+    pid: PidController,
+}
+
+impl PidStrategy {
+    pub fn new(parameters: PidParameters) -> Result<Self, ConfigError> {
+        Ok(PidStrategy {
+            pid: PidController::new(parameters)?,
+        })
+    }
+
+    pub fn parameters(&self) -> PidParameters {
+        self.pid.parameters()
+    }
+}
+
+impl ControlStrategy for PidStrategy {
+    fn decide(
+        &mut self,
+        current_temperature: f32,
+        config: &Config,
+        _current_state: SystemState,
+        elapsed: std::time::Duration,
+    ) -> ControlDecision {
+        if let Some(gains) = config.pid_gains {
+            if gains != self.pid.parameters().gains() {
+                self.pid.set_gains(gains);
+            }
+        }
+        let correction = self
+            .pid
+            .update(config.setpoint, current_temperature, elapsed);
+
+        let deadband = self.pid.parameters().deadband;
+        if correction > deadband {
+            ControlDecision::new(
+                ActuatorCommand::Raise(current_temperature + correction),
+                SystemState::Heating,
+            )
+        } else if correction < -deadband {
+            ControlDecision::new(
+                ActuatorCommand::Lower(current_temperature + correction),
+                SystemState::Cooling,
+            )
+        } else {
+            ControlDecision::new(ActuatorCommand::Hold, SystemState::Idle)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ONE_SECOND: std::time::Duration = std::time::Duration::from_secs(1);
+
+    #[test]
+    fn proportional_only() {
+        let mut pid = PidController::new(PidParameters {
+            kp: 2.0,
+            ki: 0.0,
+            kd: 0.0,
+            output_min: -10.0,
+            output_max: 10.0,
+            deadband: 0.0,
+        })
+        .unwrap();
+
+        let output = pid.update(20.0, 18.0, ONE_SECOND);
+
+        assert!(float_cmp::approx_eq!(f32, output, 4.0, epsilon = 0.000001));
+    }
+
+    #[test]
+    fn output_is_clamped() {
+        let mut pid = PidController::new(PidParameters {
+            kp: 10.0,
+            ki: 0.0,
+            kd: 0.0,
+            output_min: -1.0,
+            output_max: 1.0,
+            deadband: 0.0,
+        })
+        .unwrap();
+
+        assert!(float_cmp::approx_eq!(
+            f32,
+            pid.update(20.0, 10.0, ONE_SECOND),
+            1.0,
+            epsilon = 0.000001
+        ));
+        assert!(float_cmp::approx_eq!(
+            f32,
+            pid.update(20.0, 30.0, ONE_SECOND),
+            -1.0,
+            epsilon = 0.000001
+        ));
+    }
+
+    #[test]
+    fn integral_does_not_wind_up_while_saturated() {
+        let mut pid = PidController::new(PidParameters {
+            kp: 0.0,
+            ki: 1.0,
+            kd: 0.0,
+            output_min: -2.0,
+            output_max: 2.0,
+            deadband: 0.0,
+        })
+        .unwrap();
+
+        for _ in 0..100 {
+            pid.update(20.0, 10.0, ONE_SECOND);
+        }
+        let output = pid.update(20.0, 21.0, ONE_SECOND);
+
+        assert!(output < 2.0);
+    }
+
+    #[test]
+    fn derivative_ignores_setpoint_change() {
+        let mut pid = PidController::new(PidParameters {
+            kp: 0.0,
+            ki: 0.0,
+            kd: 1.0,
+            output_min: -10.0,
+            output_max: 10.0,
+            deadband: 0.0,
+        })
+        .unwrap();
+
+        pid.update(20.0, 18.0, ONE_SECOND);
+        let output = pid.update(25.0, 18.0, ONE_SECOND);
+
+        assert!(float_cmp::approx_eq!(f32, output, 0.0, epsilon = 0.000001));
+    }
+
+    #[test]
+    fn derivative_opposes_rising_measurement() {
+        let mut pid = PidController::new(PidParameters {
+            kp: 0.0,
+            ki: 0.0,
+            kd: 1.0,
+            output_min: -10.0,
+            output_max: 10.0,
+            deadband: 0.0,
+        })
+        .unwrap();
+
+        pid.update(20.0, 18.0, ONE_SECOND);
+        let output = pid.update(20.0, 19.0, ONE_SECOND);
+
+        assert!(float_cmp::approx_eq!(f32, output, -1.0, epsilon = 0.000001));
+    }
+
+    #[test]
+    fn strategy_heats_towards_setpoint() {
+        let mut strategy = PidStrategy::new(PidParameters {
+            kp: 0.5,
+            ki: 0.0,
+            kd: 0.0,
+            output_min: -5.0,
+            output_max: 5.0,
+            deadband: 0.0,
+        })
+        .unwrap();
+        let config = Config::from_limits(18f32, 22f32);
+
+        let decision = strategy.decide(16.0, &config, SystemState::Idle, ONE_SECOND);
+
+        assert!(
+            decision == ControlDecision::new(ActuatorCommand::Raise(18.0), SystemState::Heating)
+        );
+    }
+
+    #[test]
+    fn strategy_idles_within_deadband() {
+        let mut strategy = PidStrategy::new(PidParameters {
+            kp: 0.5,
+            ki: 0.0,
+            kd: 0.0,
+            output_min: -5.0,
+            output_max: 5.0,
+            deadband: 0.2,
+        })
+        .unwrap();
+        let config = Config::from_limits(18f32, 22f32);
+
+        for temperature in [19.7, 20.3, 19.8] {
+            let decision = strategy.decide(temperature, &config, SystemState::Idle, ONE_SECOND);
+            assert!(decision == ControlDecision::new(ActuatorCommand::Hold, SystemState::Idle));
+        }
+        let decision = strategy.decide(21.0, &config, SystemState::Idle, ONE_SECOND);
+        assert!(decision.next_state == SystemState::Cooling);
+    }
+
+    #[test]
+    fn inverted_output_range_is_rejected() {
+        let parameters = PidParameters {
+            output_min: 5.0,
+            output_max: -5.0,
+            ..PidParameters::default()
+        };
+
+        assert!(matches!(
+            PidStrategy::new(parameters),
+            Err(ConfigError::Rejected(diagnostics)) if diagnostics[0].field == "output_min"
+        ));
+        let parameters = PidParameters {
+            deadband: f32::NAN,
+            ..PidParameters::default()
+        };
+        assert!(PidController::new(parameters).is_err());
+    }
+
+    #[test]
+    fn strategy_uses_config_gains() {
+        let mut strategy = PidStrategy::new(PidParameters {
+            kp: 0.5,
+            ki: 0.0,
+            kd: 0.0,
+            output_min: -5.0,
+            output_max: 5.0,
+            deadband: 0.0,
+        })
+        .unwrap();
+        let gains = PidGains {
+            kp: 1.5,
+            ki: 0.0,
+            kd: 0.0,
+        };
+        let config = Config {
+            pid_gains: Some(gains),
+            ..Config::from_limits(18f32, 22f32)
+        };
+
+        let decision = strategy.decide(18.0, &config, SystemState::Idle, ONE_SECOND);
+
+        assert!(strategy.parameters().gains() == gains);
+        assert!(
+            decision == ControlDecision::new(ActuatorCommand::Raise(21.0), SystemState::Heating)
+        );
+    }
+}
@@ -0,0 +1,48 @@
+/// A value kept as JSON in a file so it survives restarts.
+pub struct JsonFile<T> {
+    file_name: String,
+    value: std::marker::PhantomData<fn() -> T>,
+}
+
+impl<T: serde::Serialize + serde::de::DeserializeOwned> JsonFile<T> {
+    pub fn new(file_name: String) -> Self {
+        JsonFile {
+            file_name,
+            value: std::marker::PhantomData,
+        }
+    }
+
+    /// The stored value, or `None` if nothing was stored yet.
+    pub fn read(&self) -> Result<Option<T>, std::io::Error> {
+        match std::fs::read_to_string(&self.file_name) {
+            Ok(contents) => Ok(Some(serde_json::from_str(&contents)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn write(&self, value: &T) -> Result<(), std::io::Error> {
+        std::fs::write(&self.file_name, serde_json::to_string(value)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn value_survives_restart() {
+        let file_name = std::env::temp_dir()
+            .join(format!("json_file_{}.json", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let store = JsonFile::<Vec<f32>>::new(file_name.clone());
+        assert!(matches!(store.read(), Ok(None)));
+
+        assert!(store.write(&vec![7.5, 3.0]).is_ok());
+
+        let loaded = JsonFile::<Vec<f32>>::new(file_name.clone()).read();
+        std::fs::remove_file(&file_name).unwrap();
+        assert!(matches!(loaded, Ok(Some(loaded)) if loaded == vec![7.5, 3.0]));
+    }
+}
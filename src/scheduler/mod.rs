@@ -0,0 +1,238 @@
+use crate::clock::Clock;
+use crate::temperature_controller::{ControllerError, HandleTemperature};
+
+const MIN_PERIOD: std::time::Duration = std::time::Duration::from_millis(1);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MissedTickPolicy {
+    /// Drop ticks that are already in the past and wait for the next one.
+    Skip,
+    /// Run every missed tick back to back until the schedule has caught up.
+    CatchUp,
+}
+
+/// Runs sensor sampling and control decisions at fixed rates taken from the
+/// controller's config.
+///
+/// Ticks are scheduled from the previous tick rather than from when the
+/// previous one finished, so time spent inside the controller does not make
+/// the loop drift.
+pub struct ControlLoopScheduler {
+    clock: std::sync::Arc<dyn Clock>,
+    missed_tick_policy: MissedTickPolicy,
+    next_sample: Option<std::time::Instant>,
+    next_control: Option<std::time::Instant>,
+    missed_ticks: u64,
+}
+
+impl ControlLoopScheduler {
+    pub fn new(clock: std::sync::Arc<dyn Clock>) -> Self {
+        ControlLoopScheduler {
+            clock,
+            missed_tick_policy: MissedTickPolicy::Skip,
+            next_sample: None,
+            next_control: None,
+            missed_ticks: 0,
+        }
+    }
+
+    pub fn with_missed_tick_policy(mut self, missed_tick_policy: MissedTickPolicy) -> Self {
+        self.missed_tick_policy = missed_tick_policy;
+        self
+    }
+
+    pub fn get_missed_ticks(&self) -> u64 {
+        self.missed_ticks
+    }
+
+    pub fn run(
+        &mut self,
+        controller: &mut dyn HandleTemperature,
+        mut on_error: impl FnMut(ControllerError),
+    ) -> ! {
+        loop {
+            if let Err(err) = self.run_once(controller) {
+                on_error(err);
+            }
+        }
+    }
+
+    /// Waits for the next due tick and runs it. Samples are only taken when the
+    /// sample period is shorter than the control period; otherwise each control
+    /// decision reads the sensor itself. A control error is reported in
+    /// preference to a sampling error.
+    pub fn run_once(
+        &mut self,
+        controller: &mut dyn HandleTemperature,
+    ) -> Result<(), ControllerError> {
+        let config = controller.get_config().unwrap_or_default();
+        let sample_period = config.sample_period.max(MIN_PERIOD);
+        let control_period = config.control_period.max(MIN_PERIOD);
+
+        let now = self.clock.now();
+        let next_control = *self.next_control.get_or_insert(now);
+        let next_sample = if sample_period < control_period {
+            Some(*self.next_sample.get_or_insert(now))
+        } else {
+            self.next_sample = None;
+            None
+        };
+        let due = next_sample.map_or(next_control, |next_sample| next_sample.min(next_control));
+        if due > now {
+            self.clock.sleep(due - now);
+        }
+
+        let now = self.clock.now();
+        let mut sample_result = Ok(());
+        if let Some(next_sample) = next_sample.filter(|next_sample| now >= *next_sample) {
+            sample_result = controller.sample_temperature();
+            self.next_sample = Some(self.advance(next_sample, sample_period, now));
+        }
+        let mut control_result = Ok(());
+        if now >= next_control {
+            control_result = controller.update_temperature();
+            let now = self.clock.now();
+            self.next_control = Some(self.advance(next_control, control_period, now));
+        }
+        control_result.and(sample_result)
+    }
+
+    fn advance(
+        &mut self,
+        scheduled: std::time::Instant,
+        period: std::time::Duration,
+        now: std::time::Instant,
+    ) -> std::time::Instant {
+        let next = scheduled + period;
+        if next > now || self.missed_tick_policy == MissedTickPolicy::CatchUp {
+            return next;
+        }
+        let behind = (now - next).as_nanos();
+        let missed = behind / period.as_nanos() + 1;
+        self.missed_ticks = self
+            .missed_ticks
+            .saturating_add(u64::try_from(missed).unwrap_or(u64::MAX));
+        // Resynced from `now` in the same phase, so a long stall or clock jump
+        // does not have to be multiplied out.
+        let into_period = behind % period.as_nanos();
+        now + u64::try_from(into_period).map_or(period, |into_period| {
+            period - std::time::Duration::from_nanos(into_period)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::config_reader::Config;
+    use crate::temperature_controller::MockHandleTemperature;
+
+    type Recorded = std::sync::Arc<std::sync::Mutex<Vec<u64>>>;
+
+    fn controller_with_periods(
+        clock: std::sync::Arc<ManualClock>,
+        control_period: u64,
+        sample_period: u64,
+        control_duration: u64,
+    ) -> (MockHandleTemperature, Recorded, Recorded) {
+        let controls = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let samples = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut controller = MockHandleTemperature::new();
+        controller.expect_get_config().returning(move || {
+            Some(Config {
+                control_period: std::time::Duration::from_secs(control_period),
+                sample_period: std::time::Duration::from_secs(sample_period),
+                ..Config::default()
+            })
+        });
+        let control_clock = clock.clone();
+        let recorded_controls = controls.clone();
+        controller.expect_update_temperature().returning(move || {
+            recorded_controls
+                .lock()
+                .unwrap()
+                .push(control_clock.elapsed().as_secs());
+            control_clock.advance(std::time::Duration::from_secs(control_duration));
+            Ok(())
+        });
+        let recorded_samples = samples.clone();
+        controller.expect_sample_temperature().returning(move || {
+            recorded_samples
+                .lock()
+                .unwrap()
+                .push(clock.elapsed().as_secs());
+            Ok(())
+        });
+        (controller, controls, samples)
+    }
+
+    #[test]
+    fn time_spent_controlling_does_not_drift() {
+        let clock = std::sync::Arc::new(ManualClock::default());
+        let (mut controller, controls, _) = controller_with_periods(clock.clone(), 3, 3, 1);
+        let mut scheduler = ControlLoopScheduler::new(clock);
+
+        for _ in 0..4 {
+            assert!(scheduler.run_once(&mut controller).is_ok());
+        }
+
+        assert!(*controls.lock().unwrap() == vec![0, 3, 6, 9]);
+    }
+
+    #[test]
+    fn missed_ticks_are_skipped() {
+        let clock = std::sync::Arc::new(ManualClock::default());
+        let (mut controller, controls, _) = controller_with_periods(clock.clone(), 3, 3, 7);
+        let mut scheduler = ControlLoopScheduler::new(clock);
+
+        assert!(scheduler.run_once(&mut controller).is_ok());
+        assert!(scheduler.get_missed_ticks() == 2);
+        assert!(scheduler.run_once(&mut controller).is_ok());
+
+        assert!(*controls.lock().unwrap() == vec![0, 9]);
+    }
+
+    #[test]
+    fn long_stall_is_skipped_in_phase() {
+        let clock = std::sync::Arc::new(ManualClock::default());
+        let mut scheduler = ControlLoopScheduler::new(clock.clone());
+        let start = clock.now();
+        let period = std::time::Duration::from_secs(3);
+        let stall = period * u32::MAX + std::time::Duration::from_secs(7);
+
+        let next = scheduler.advance(start, period, start + stall);
+
+        assert!(next == start + period * u32::MAX + std::time::Duration::from_secs(9));
+        assert!(scheduler.get_missed_ticks() == u64::from(u32::MAX) + 2);
+    }
+
+    #[test]
+    fn missed_ticks_can_catch_up() {
+        let clock = std::sync::Arc::new(ManualClock::default());
+        let (mut controller, controls, _) = controller_with_periods(clock.clone(), 3, 3, 7);
+        let mut scheduler =
+            ControlLoopScheduler::new(clock).with_missed_tick_policy(MissedTickPolicy::CatchUp);
+
+        for _ in 0..2 {
+            assert!(scheduler.run_once(&mut controller).is_ok());
+        }
+
+        assert!(*controls.lock().unwrap() == vec![0, 7]);
+        assert!(scheduler.get_missed_ticks() == 0);
+    }
+
+    #[test]
+    fn samples_faster_than_it_controls() {
+        let clock = std::sync::Arc::new(ManualClock::default());
+        let (mut controller, controls, samples) = controller_with_periods(clock.clone(), 3, 1, 0);
+        let mut scheduler = ControlLoopScheduler::new(clock);
+
+        for _ in 0..7 {
+            assert!(scheduler.run_once(&mut controller).is_ok());
+        }
+
+        assert!(*samples.lock().unwrap() == vec![0, 1, 2, 3, 4, 5, 6]);
+        assert!(*controls.lock().unwrap() == vec![0, 3, 6]);
+    }
+}
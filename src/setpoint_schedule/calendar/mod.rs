@@ -0,0 +1,322 @@
+use chrono::TimeZone;
+
+use crate::config_reader::validation::{validate_band, Diagnostic};
+use crate::config_reader::Config;
+use crate::json_file::JsonFile;
+
+#[derive(Debug)]
+pub enum CalendarError {
+    /// The vacation breaks one or more invariants and was not added.
+    Rejected(Vec<Diagnostic>),
+    /// The calendar was changed but could not be stored.
+    Unsaved(std::io::Error),
+}
+
+impl std::fmt::Display for CalendarError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CalendarError::Rejected(diagnostics) => {
+                write!(f, "Vacation is invalid: ")?;
+                for (index, diagnostic) in diagnostics.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{diagnostic}")?;
+                }
+                Ok(())
+            }
+            CalendarError::Unsaved(_) => write!(f, "Failed to store the calendar"),
+        }
+    }
+}
+
+impl std::error::Error for CalendarError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CalendarError::Rejected(_) => None,
+            CalendarError::Unsaved(err) => Some(err),
+        }
+    }
+}
+
+impl From<std::io::Error> for CalendarError {
+    fn from(err: std::io::Error) -> Self {
+        CalendarError::Unsaved(err)
+    }
+}
+
+/// Time away from `start` until the `end` return date, both local dates. The
+/// band holds from midnight on `start` until midnight on `end`, less
+/// `pre_heat` so the normal program has time to bring the temperature back.
+#[derive(Clone, Copy, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Vacation {
+    pub start: chrono::NaiveDate,
+    pub end: chrono::NaiveDate,
+    pub min_temperature: f32,
+    pub max_temperature: f32,
+    pub pre_heat: std::time::Duration,
+}
+
+impl Vacation {
+    /// Checks the band like the config's and that the vacation does not end
+    /// before it starts.
+    pub fn validate(&self) -> Result<(), CalendarError> {
+        let mut diagnostics = validate_band(self.min_temperature, self.max_temperature);
+        if self.start > self.end {
+            diagnostics.push(Diagnostic {
+                field: "end".to_string(),
+                message: format!("{} is before start {}", self.end, self.start),
+                location: None,
+            });
+        }
+        match diagnostics.is_empty() {
+            true => Ok(()),
+            false => Err(CalendarError::Rejected(diagnostics)),
+        }
+    }
+
+    /// `config` with its band replaced by the vacation band.
+    pub fn apply(&self, config: &Config) -> Config {
+        let band = Config::from_limits(self.min_temperature, self.max_temperature);
+        Config {
+            setpoint: band.setpoint,
+            heating_deadband: band.heating_deadband,
+            cooling_deadband: band.cooling_deadband,
+            ..*config
+        }
+    }
+}
+
+/// Date-based overrides applied on top of the config and schedule. Dates are
+/// taken in the calendar's own timezone if it has one, and otherwise in the
+/// timezone passed to `active_vacation`, normally the schedule's.
+#[derive(Clone, PartialEq, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Calendar {
+    #[serde(skip)]
+    timezone: Option<chrono_tz::Tz>,
+    vacations: Vec<Vacation>,
+    holidays: Vec<chrono::NaiveDate>,
+}
+
+impl Calendar {
+    pub fn new(timezone: chrono_tz::Tz) -> Self {
+        Calendar {
+            timezone: Some(timezone),
+            ..Calendar::default()
+        }
+    }
+
+    /// Takes the vacations and holidays of `saved`, keeping the timezone.
+    pub fn restore(&mut self, saved: Calendar) {
+        self.vacations = saved.vacations;
+        self.holidays = saved.holidays;
+    }
+
+    pub fn add_vacation(&mut self, vacation: Vacation) -> Result<(), CalendarError> {
+        vacation.validate()?;
+        self.vacations.push(vacation);
+        Ok(())
+    }
+
+    /// Public holidays, which run the weekend program of the schedule.
+    pub fn add_holiday(&mut self, date: chrono::NaiveDate) {
+        self.holidays.push(date);
+    }
+
+    pub fn holidays(&self) -> &[chrono::NaiveDate] {
+        &self.holidays
+    }
+
+    /// The vacation in force at `at` and when it ends, if any.
+    /// `default_timezone` is used if the calendar has none of its own.
+    pub fn active_vacation(
+        &self,
+        at: chrono::DateTime<chrono::Utc>,
+        default_timezone: chrono_tz::Tz,
+    ) -> Option<(Vacation, chrono::DateTime<chrono::Utc>)> {
+        let timezone = self.timezone.unwrap_or(default_timezone);
+        self.vacations.iter().find_map(|vacation| {
+            let starts = midnight(timezone, vacation.start);
+            let ends = midnight(timezone, vacation.end) - vacation.pre_heat;
+            (starts <= at && at < ends).then_some((*vacation, ends))
+        })
+    }
+}
+
+fn midnight(timezone: chrono_tz::Tz, date: chrono::NaiveDate) -> chrono::DateTime<chrono::Utc> {
+    let local = date.and_time(chrono::NaiveTime::MIN);
+    timezone
+        .from_local_datetime(&local)
+        .earliest()
+        .unwrap_or_else(|| timezone.from_utc_datetime(&local))
+        .with_timezone(&chrono::Utc)
+}
+
+/// Keeps vacations and holidays across restarts.
+#[mockall::automock]
+pub trait PersistCalendar {
+    fn load(&self) -> Result<Option<Calendar>, std::io::Error>;
+    fn save(&self, calendar: &Calendar) -> Result<(), std::io::Error>;
+}
+
+pub type CalendarFile = JsonFile<Calendar>;
+
+impl PersistCalendar for CalendarFile {
+    fn load(&self) -> Result<Option<Calendar>, std::io::Error> {
+        self.read()
+    }
+
+    fn save(&self, calendar: &Calendar) -> Result<(), std::io::Error> {
+        self.write(calendar)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_reader::Mode;
+
+    fn utc(value: &str) -> chrono::DateTime<chrono::Utc> {
+        chrono::DateTime::parse_from_rfc3339(value)
+            .unwrap()
+            .with_timezone(&chrono::Utc)
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> chrono::NaiveDate {
+        chrono::NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn summer_vacation(pre_heat: std::time::Duration) -> Calendar {
+        let mut calendar = Calendar::new(chrono_tz::Europe::Berlin);
+        let added = calendar.add_vacation(Vacation {
+            start: date(2024, 7, 1),
+            end: date(2024, 7, 15),
+            min_temperature: 12.0,
+            max_temperature: 28.0,
+            pre_heat,
+        });
+        assert!(added.is_ok());
+        calendar
+    }
+
+    #[test]
+    fn invalid_vacations_are_rejected() {
+        let mut calendar = summer_vacation(std::time::Duration::ZERO);
+        let vacation = Vacation {
+            start: date(2024, 8, 1),
+            end: date(2024, 8, 10),
+            min_temperature: 12.0,
+            max_temperature: 28.0,
+            pre_heat: std::time::Duration::ZERO,
+        };
+        let rejected_fields =
+            |calendar: &mut Calendar, vacation| match calendar.add_vacation(vacation) {
+                Err(CalendarError::Rejected(diagnostics)) => diagnostics
+                    .into_iter()
+                    .map(|diagnostic| diagnostic.field)
+                    .collect(),
+                _ => Vec::new(),
+            };
+
+        let inverted = Vacation {
+            min_temperature: 28.0,
+            max_temperature: 12.0,
+            ..vacation
+        };
+        assert!(rejected_fields(&mut calendar, inverted) == vec!["min_temperature"]);
+        let not_finite = Vacation {
+            max_temperature: f32::INFINITY,
+            ..vacation
+        };
+        assert!(rejected_fields(&mut calendar, not_finite) == vec!["max_temperature"]);
+        let backwards = Vacation {
+            start: vacation.end,
+            end: vacation.start,
+            ..vacation
+        };
+        assert!(rejected_fields(&mut calendar, backwards) == vec!["end"]);
+        assert!(calendar
+            .active_vacation(utc("2024-08-05T12:00:00Z"), chrono_tz::UTC)
+            .is_none());
+    }
+
+    #[test]
+    fn vacation_covers_date_range() {
+        let calendar = summer_vacation(std::time::Duration::ZERO);
+
+        // Berlin is two hours ahead of UTC in summer.
+        assert!(calendar
+            .active_vacation(utc("2024-06-30T21:59:00Z"), chrono_tz::UTC)
+            .is_none());
+        let active = calendar.active_vacation(utc("2024-06-30T22:00:00Z"), chrono_tz::UTC);
+        assert!(active.map(|(_, ends)| ends) == Some(utc("2024-07-14T22:00:00Z")));
+        assert!(calendar
+            .active_vacation(utc("2024-07-14T22:00:00Z"), chrono_tz::UTC)
+            .is_none());
+    }
+
+    #[test]
+    fn pre_heat_ends_vacation_early() {
+        let calendar = summer_vacation(std::time::Duration::from_secs(3 * 3600));
+
+        assert!(calendar
+            .active_vacation(utc("2024-07-14T18:59:00Z"), chrono_tz::UTC)
+            .is_some());
+        assert!(calendar
+            .active_vacation(utc("2024-07-14T19:00:00Z"), chrono_tz::UTC)
+            .is_none());
+    }
+
+    #[test]
+    fn vacation_follows_default_timezone() {
+        let mut calendar = Calendar::default();
+        calendar.restore(summer_vacation(std::time::Duration::ZERO));
+
+        assert!(calendar
+            .active_vacation(utc("2024-06-30T22:00:00Z"), chrono_tz::Europe::Berlin)
+            .is_some());
+        assert!(calendar
+            .active_vacation(utc("2024-06-30T22:00:00Z"), chrono_tz::UTC)
+            .is_none());
+    }
+
+    #[test]
+    fn restored_calendar_keeps_timezone() {
+        let mut calendar = summer_vacation(std::time::Duration::from_secs(3600));
+        calendar.add_holiday(date(2024, 12, 25));
+
+        let saved: Calendar =
+            serde_json::from_str(&serde_json::to_string(&calendar).unwrap()).unwrap();
+        let mut restored = Calendar::new(chrono_tz::Europe::Berlin);
+        restored.restore(saved);
+        assert!(restored == calendar);
+    }
+
+    #[test]
+    fn vacation_band_keeps_mode() {
+        let calendar = summer_vacation(std::time::Duration::ZERO);
+        let config = Config {
+            mode: Mode::HeatOnly,
+            ..Config::default()
+        };
+
+        let (vacation, _) = calendar
+            .active_vacation(utc("2024-07-05T12:00:00Z"), chrono_tz::UTC)
+            .unwrap();
+        let config = vacation.apply(&config);
+
+        assert!(config.mode == Mode::HeatOnly);
+        assert!(float_cmp::approx_eq!(
+            f32,
+            config.min_temperature(),
+            12f32,
+            epsilon = 0.000001
+        ));
+        assert!(float_cmp::approx_eq!(
+            f32,
+            config.max_temperature(),
+            28f32,
+            epsilon = 0.000001
+        ));
+    }
+}
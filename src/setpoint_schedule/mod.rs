@@ -0,0 +1,431 @@
+use chrono::{Datelike, TimeZone};
+
+use crate::config_reader::validation::temperature_problem;
+use crate::config_reader::ConfigError;
+
+pub mod calendar;
+
+/// A setpoint that takes over at `start` local time on `weekday` and lasts
+/// until the next period begins.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SchedulePeriod {
+    pub name: String,
+    pub weekday: chrono::Weekday,
+    pub start: chrono::NaiveTime,
+    pub setpoint: f32,
+}
+
+/// Periods repeating every week, in the wall-clock time of `timezone` so they
+/// follow daylight saving changes. Holidays run the Sunday program.
+#[derive(Clone, PartialEq, Debug)]
+pub struct WeeklySchedule {
+    timezone: chrono_tz::Tz,
+    periods: Vec<SchedulePeriod>,
+    holidays: Vec<chrono::NaiveDate>,
+}
+
+impl WeeklySchedule {
+    pub fn new(timezone: chrono_tz::Tz) -> Self {
+        WeeklySchedule {
+            timezone,
+            periods: Vec::new(),
+            holidays: Vec::new(),
+        }
+    }
+
+    pub fn add_period(&mut self, period: SchedulePeriod) {
+        self.periods.push(period);
+        self.periods.sort_by_key(|period| period.start);
+    }
+
+    pub fn add_holiday(&mut self, date: chrono::NaiveDate) {
+        if !self.holidays.contains(&date) {
+            self.holidays.push(date);
+        }
+    }
+
+    pub fn timezone(&self) -> chrono_tz::Tz {
+        self.timezone
+    }
+
+    pub fn periods(&self) -> &[SchedulePeriod] {
+        &self.periods
+    }
+
+    /// The period in force at `at`, carrying over from earlier days until the
+    /// first period of the day starts.
+    pub fn active_period(&self, at: chrono::DateTime<chrono::Utc>) -> Option<&SchedulePeriod> {
+        let local = at.with_timezone(&self.timezone);
+        (0..=7).find_map(|days_back| {
+            let date = local.date_naive() - chrono::Duration::days(days_back);
+            self.periods_on(date)
+                .filter(|period| days_back > 0 || period.start <= local.time())
+                .last()
+        })
+    }
+
+    /// The period after the one in force at `at` and when it begins.
+    pub fn next_period(
+        &self,
+        at: chrono::DateTime<chrono::Utc>,
+    ) -> Option<(&SchedulePeriod, chrono::DateTime<chrono::Utc>)> {
+        let local = at.with_timezone(&self.timezone);
+        (0..=7).find_map(|days_ahead| {
+            let date = local.date_naive() + chrono::Duration::days(days_ahead);
+            self.periods_on(date)
+                .find(|period| days_ahead > 0 || period.start > local.time())
+                .map(|period| (period, self.to_utc(date.and_time(period.start))))
+        })
+    }
+
+    pub fn next_period_start(
+        &self,
+        at: chrono::DateTime<chrono::Utc>,
+    ) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.next_period(at).map(|(_, start)| start)
+    }
+
+    fn periods_on(&self, date: chrono::NaiveDate) -> impl Iterator<Item = &SchedulePeriod> {
+        let weekday = match self.holidays.contains(&date) {
+            true => chrono::Weekday::Sun,
+            false => date.weekday(),
+        };
+        self.periods
+            .iter()
+            .filter(move |period| period.weekday == weekday)
+    }
+
+    /// Resolves a local time, taking the earlier instant when clocks go back
+    /// and the end of the gap when they go forward, however long it is.
+    fn to_utc(&self, local: chrono::NaiveDateTime) -> chrono::DateTime<chrono::Utc> {
+        let mut candidate = local;
+        while candidate < local + chrono::Duration::days(1) {
+            if let Some(resolved) = self.timezone.from_local_datetime(&candidate).earliest() {
+                return resolved.with_timezone(&chrono::Utc);
+            }
+            candidate += chrono::Duration::minutes(1);
+        }
+        // No timezone skips a whole day, but a schedule must not stop the loop.
+        chrono::Utc.from_utc_datetime(&local)
+    }
+}
+
+/// Overrides the schedule until the next period starts or until cleared.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Hold {
+    Temporary {
+        setpoint: f32,
+        until: Option<chrono::DateTime<chrono::Utc>>,
+    },
+    Permanent {
+        setpoint: f32,
+    },
+}
+
+/// Where the setpoint the controller acts on came from.
+#[derive(Clone, PartialEq, Debug)]
+pub enum SetpointSource {
+    Config,
+    Schedule {
+        period: String,
+    },
+    Hold(Hold),
+    /// A vacation band applies until `until`.
+    Vacation {
+        until: chrono::DateTime<chrono::Utc>,
+    },
+    /// Started early so `period`'s setpoint is reached when it begins.
+    OptimalStart {
+        period: String,
+    },
+}
+
+/// Picks the setpoint for `now`: an active hold wins over the schedule, which
+/// wins over the config's own setpoint. A temporary hold that has run out is
+/// cleared.
+pub fn resolve_setpoint(
+    config_setpoint: f32,
+    schedule: Option<&WeeklySchedule>,
+    hold: &mut Option<Hold>,
+    now: chrono::DateTime<chrono::Utc>,
+) -> (f32, SetpointSource) {
+    if let Some(Hold::Temporary {
+        until: Some(until), ..
+    }) = *hold
+    {
+        if now >= until {
+            *hold = None;
+        }
+    }
+    if let Some(active_hold) = *hold {
+        let setpoint = match active_hold {
+            Hold::Temporary { setpoint, .. } | Hold::Permanent { setpoint } => setpoint,
+        };
+        return (setpoint, SetpointSource::Hold(active_hold));
+    }
+    match schedule.and_then(|schedule| schedule.active_period(now)) {
+        Some(period) => (
+            period.setpoint,
+            SetpointSource::Schedule {
+                period: period.name.clone(),
+            },
+        ),
+        None => (config_setpoint, SetpointSource::Config),
+    }
+}
+
+#[mockall::automock]
+pub trait ReadSchedule {
+    /// The stored schedule, or `None` when there is none and the config's own
+    /// setpoint applies.
+    fn get_schedule(&self) -> Result<Option<WeeklySchedule>, ConfigError>;
+}
+
+/// Accepts `mon`..`sun`, comma separated lists of them, `weekdays`, `weekend`
+/// and `daily`.
+pub fn parse_weekdays(value: &str) -> Result<Vec<chrono::Weekday>, ConfigError> {
+    use chrono::Weekday::*;
+    let mut weekdays = Vec::new();
+    for day in value.split(',') {
+        match day.trim().to_ascii_lowercase().as_str() {
+            "weekdays" => weekdays.extend([Mon, Tue, Wed, Thu, Fri]),
+            "weekend" => weekdays.extend([Sat, Sun]),
+            "daily" => weekdays.extend([Mon, Tue, Wed, Thu, Fri, Sat, Sun]),
+            day => weekdays.push(day.parse::<chrono::Weekday>().map_err(|_| {
+                ConfigError::Invalid(format!("Failed to convert {day} to a weekday"))
+            })?),
+        }
+    }
+    Ok(weekdays)
+}
+
+pub fn parse_date(value: &str) -> Result<chrono::NaiveDate, ConfigError> {
+    chrono::NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+        .map_err(|err| ConfigError::Invalid(format!("Failed to convert {value} to a date: {err}")))
+}
+
+pub fn parse_timezone(value: &str) -> Result<chrono_tz::Tz, ConfigError> {
+    value
+        .trim()
+        .parse::<chrono_tz::Tz>()
+        .map_err(|err| ConfigError::Invalid(format!("Failed to convert timezone: {err}")))
+}
+
+/// Adds one period for each of `weekdays`, e.g. from `weekdays 06:30 wake 21`.
+pub fn add_periods(
+    schedule: &mut WeeklySchedule,
+    weekdays: &str,
+    start: &str,
+    name: &str,
+    setpoint: &str,
+) -> Result<(), ConfigError> {
+    let start = chrono::NaiveTime::parse_from_str(start.trim(), "%H:%M").map_err(|err| {
+        ConfigError::Invalid(format!("Failed to convert {start} to a time of day: {err}"))
+    })?;
+    let setpoint = setpoint.trim().parse::<f32>().map_err(|err| {
+        ConfigError::Invalid(format!(
+            "Failed to convert setpoint of {name} to a number: {err}"
+        ))
+    })?;
+    // Scheduled setpoints become the effective setpoint like the config's.
+    if let Some(problem) = temperature_problem(setpoint) {
+        return Err(ConfigError::Invalid(format!(
+            "Setpoint of {name} is invalid: {problem}"
+        )));
+    }
+    for weekday in parse_weekdays(weekdays)? {
+        schedule.add_period(SchedulePeriod {
+            name: name.to_string(),
+            weekday,
+            start,
+            setpoint,
+        });
+    }
+    Ok(())
+}
+
+/// Parses a `timezone=<name>` line followed by `<days> <HH:MM> <name> <setpoint>`
+/// lines and optional `holidays=<YYYY-MM-DD>,...` lines.
+pub fn parse_schedule_lines<'a>(
+    lines: impl IntoIterator<Item = &'a str>,
+) -> Result<WeeklySchedule, ConfigError> {
+    let mut lines = lines.into_iter().filter(|line| !line.trim().is_empty());
+    let timezone = match lines
+        .next()
+        .and_then(|line| line.trim().strip_prefix("timezone="))
+    {
+        Some(timezone) => parse_timezone(timezone)?,
+        None => {
+            return Err(ConfigError::Invalid(
+                "Schedule must start with timezone=<name>".to_string(),
+            ))
+        }
+    };
+    let mut schedule = WeeklySchedule::new(timezone);
+    for line in lines {
+        if let Some(holidays) = line.trim().strip_prefix("holidays=") {
+            for date in holidays.split(',') {
+                schedule.add_holiday(parse_date(date)?);
+            }
+            continue;
+        }
+        match line.split_whitespace().collect::<Vec<&str>>()[..] {
+            [weekdays, start, name, setpoint] => {
+                add_periods(&mut schedule, weekdays, start, name, setpoint).map_err(
+                    |err| match err {
+                        ConfigError::Invalid(reason) => ConfigError::Invalid(format!(
+                            "{reason} in schedule line {}",
+                            line.trim()
+                        )),
+                        err => err,
+                    },
+                )?
+            }
+            _ => {
+                return Err(ConfigError::Invalid(format!(
+                    "Expected <days> <HH:MM> <name> <setpoint> but received {}",
+                    line.trim()
+                )))
+            }
+        }
+    }
+    Ok(schedule)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(value: &str) -> chrono::DateTime<chrono::Utc> {
+        chrono::DateTime::parse_from_rfc3339(value)
+            .unwrap()
+            .with_timezone(&chrono::Utc)
+    }
+
+    fn home_schedule() -> WeeklySchedule {
+        let schedule = parse_schedule_lines([
+            "timezone=Europe/London",
+            "weekdays 06:30 wake 21",
+            "weekdays 22:00 sleep 17",
+            "weekend 08:00 wake 21.5",
+            "weekend 23:00 sleep 17",
+        ]);
+        assert!(schedule.is_ok());
+        schedule.unwrap()
+    }
+
+    #[test]
+    fn active_period_follows_time_of_day() {
+        let schedule = home_schedule();
+
+        // Monday 2024-01-08
+        let period = schedule.active_period(utc("2024-01-08T07:00:00Z"));
+        assert!(period.map(|period| period.name.as_str()) == Some("wake"));
+        let period = schedule.active_period(utc("2024-01-08T23:00:00Z"));
+        assert!(period.map(|period| period.setpoint) == Some(17f32));
+    }
+
+    #[test]
+    fn early_monday_wraps_to_sunday_night() {
+        let schedule = home_schedule();
+
+        let period = schedule.active_period(utc("2024-01-08T03:00:00Z"));
+        assert!(period.map(|period| period.weekday) == Some(chrono::Weekday::Sun));
+        let next = schedule.next_period_start(utc("2024-01-08T03:00:00Z"));
+        assert!(next == Some(utc("2024-01-08T06:30:00Z")));
+    }
+
+    #[test]
+    fn periods_follow_daylight_saving() {
+        let schedule = home_schedule();
+
+        // Clocks went forward on Sunday 2024-03-31, so 06:30 local is 05:30 UTC.
+        let next = schedule.next_period_start(utc("2024-03-31T23:00:00Z"));
+        assert!(next == Some(utc("2024-04-01T05:30:00Z")));
+        let period = schedule.active_period(utc("2024-04-01T05:45:00Z"));
+        assert!(period.map(|period| period.name.as_str()) == Some("wake"));
+    }
+
+    #[test]
+    fn periods_in_long_gaps_start_when_it_ends() {
+        let mut schedule = WeeklySchedule::new(chrono_tz::Antarctica::Troll);
+        assert!(add_periods(&mut schedule, "sun", "02:00", "wake", "20").is_ok());
+
+        // Troll skips from 01:00 to 03:00 local, 01:00 UTC, on 2024-03-31.
+        let next = schedule.next_period_start(utc("2024-03-30T23:00:00Z"));
+        assert!(next == Some(utc("2024-03-31T01:00:00Z")));
+    }
+
+    #[test]
+    fn holidays_run_sunday_program() {
+        let mut schedule = home_schedule();
+        // Monday 2024-12-30 at 07:00, after the weekday wake period started.
+        let at = utc("2024-12-30T07:00:00Z");
+        assert!(schedule.active_period(at).map(|period| period.setpoint) == Some(21f32));
+
+        schedule.add_holiday(chrono::NaiveDate::from_ymd_opt(2024, 12, 30).unwrap());
+
+        let period = schedule.active_period(at);
+        assert!(period.map(|period| period.weekday) == Some(chrono::Weekday::Sun));
+        assert!(period.map(|period| period.name.as_str()) == Some("sleep"));
+        let next = schedule.next_period_start(at);
+        assert!(next == Some(utc("2024-12-30T08:00:00Z")));
+    }
+
+    #[test]
+    fn holds_override_schedule() {
+        let schedule = home_schedule();
+        let now = utc("2024-01-08T07:00:00Z");
+
+        let mut hold = Some(Hold::Temporary {
+            setpoint: 19f32,
+            until: schedule.next_period_start(now),
+        });
+        let (setpoint, _) = resolve_setpoint(20f32, Some(&schedule), &mut hold, now);
+        assert!(setpoint == 19f32);
+
+        let (setpoint, source) = resolve_setpoint(
+            20f32,
+            Some(&schedule),
+            &mut hold,
+            utc("2024-01-08T22:00:00Z"),
+        );
+        assert!(setpoint == 17f32);
+        assert!(
+            source
+                == SetpointSource::Schedule {
+                    period: "sleep".to_string()
+                }
+        );
+        assert!(hold.is_none());
+
+        let mut hold = Some(Hold::Permanent { setpoint: 23f32 });
+        let (setpoint, _) = resolve_setpoint(
+            20f32,
+            Some(&schedule),
+            &mut hold,
+            utc("2024-01-09T22:00:00Z"),
+        );
+        assert!(setpoint == 23f32);
+    }
+
+    #[test]
+    fn garbage_schedule_is_invalid() {
+        let schedule = parse_schedule_lines(["timezone=Europe/London", "someday 06:30 wake 21"]);
+        assert!(matches!(schedule, Err(ConfigError::Invalid(_))));
+
+        let schedule = parse_schedule_lines(["weekdays 06:30 wake 21"]);
+        assert!(matches!(schedule, Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn scheduled_setpoints_are_validated() {
+        for setpoint in ["NaN", "inf", "500"] {
+            let line = format!("weekdays 06:30 wake {setpoint}");
+            let schedule = parse_schedule_lines(["timezone=Europe/London", line.as_str()]);
+            assert!(
+                matches!(schedule, Err(ConfigError::Invalid(message)) if message.ends_with(&line))
+            );
+        }
+    }
+}
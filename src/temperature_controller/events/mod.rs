@@ -0,0 +1,142 @@
+use crate::config_reader::{Config, ConfigError};
+use crate::control_strategy::pid::autotune::AutoTuneResult;
+use crate::setpoint_schedule::SetpointSource;
+use crate::temperature_controller::fail_safe::SafeState;
+use crate::temperature_controller::safety::SafetyLimit;
+use crate::temperature_controller::short_cycle::DeferredTransition;
+use crate::temperature_controller::staging::Stage;
+use crate::temperature_controller::SystemState;
+use crate::temperature_modifier::ActuatorError;
+use crate::temperature_sensor::SensorError;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TransitionReason {
+    /// The control strategy asked for the transition.
+    Strategy,
+    /// The active mode does not allow the state the strategy asked for.
+    ModeRestriction,
+    /// The inputs failed for longer than the fail-safe policy allows.
+    FailSafe,
+    /// A hard frost or overheat limit was reached.
+    SafetyLimit,
+    /// The actuator was switched off because the plant stopped responding.
+    ActuatorFault,
+}
+
+#[derive(Debug)]
+pub enum ControllerEvent<'a> {
+    ReadingTaken {
+        temperature: f32,
+    },
+    StateChanged {
+        from: SystemState,
+        to: SystemState,
+        reason: TransitionReason,
+    },
+    TransitionDeferred(DeferredTransition),
+    ConfigChanged {
+        previous: Option<&'a Config>,
+        current: &'a Config,
+    },
+    /// A changed config failed to load; the last good one stays in use.
+    ConfigRejected {
+        error: &'a ConfigError,
+    },
+    /// The config could not be refreshed; the cached one, `age` old, stays
+    /// in use.
+    ConfigStale {
+        error: &'a ConfigError,
+        age: std::time::Duration,
+    },
+    SensorFailed {
+        error: &'a SensorError,
+    },
+    ActuatorFailed {
+        error: &'a ActuatorError,
+    },
+    /// A run stalled or timed out and the actuator was switched off.
+    ActuatorFault {
+        error: &'a ActuatorError,
+    },
+    FailSafeEngaged {
+        safe_state: SafeState,
+    },
+    FailSafeRecovered,
+    /// A safety limit started overriding the strategy.
+    SafetyLimitTriggered {
+        limit: SafetyLimit,
+        temperature: f32,
+    },
+    /// A safety limit stopped overriding the strategy.
+    SafetyLimitCleared {
+        limit: SafetyLimit,
+        temperature: f32,
+    },
+    /// A stage was brought in or let go; `stage` is now the highest running.
+    StageChanged {
+        state: SystemState,
+        stage: Stage,
+    },
+    /// A scheduled period started or a hold was placed or ended.
+    SetpointSourceChanged {
+        source: &'a SetpointSource,
+    },
+    /// Auto-tuning measured the plant; its gains are in use from now on.
+    AutoTuneCompleted {
+        result: AutoTuneResult,
+    },
+    AutoTuneFailed {
+        reason: &'a str,
+    },
+    /// Learned state could not be saved; control carries on regardless.
+    PersistenceFailed {
+        error: &'a std::io::Error,
+    },
+    /// Tuned gains could not be written to the config. They stay in use and
+    /// the write is retried on the next cycle.
+    TunedGainsUnsaved {
+        error: &'a ConfigError,
+    },
+}
+
+pub type Subscriber = Box<dyn FnMut(&ControllerEvent)>;
+
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Vec<Subscriber>,
+}
+
+impl EventBus {
+    pub fn subscribe(&mut self, subscriber: Subscriber) {
+        self.subscribers.push(subscriber);
+    }
+
+    pub fn publish(&mut self, event: &ControllerEvent) {
+        for subscriber in self.subscribers.iter_mut() {
+            subscriber(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_subscriber_receives_event() {
+        let received = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let mut event_bus = EventBus::default();
+        for id in 0..2 {
+            let received = received.clone();
+            event_bus.subscribe(Box::new(move |event| {
+                if let ControllerEvent::ReadingTaken { temperature } = event {
+                    received.borrow_mut().push((id, *temperature));
+                }
+            }));
+        }
+
+        event_bus.publish(&ControllerEvent::ReadingTaken { temperature: 4.0 });
+
+        assert!(*received.borrow() == vec![(0, 4.0), (1, 4.0)]);
+    }
+}
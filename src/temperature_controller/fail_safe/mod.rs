@@ -0,0 +1,66 @@
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SafeState {
+    /// Stop heating and cooling.
+    AllOff,
+    /// Keep heating towards `temperature` so pipes do not freeze.
+    FrostProtect { temperature: f32 },
+}
+
+/// What the controller does when it cannot read its config or sensor.
+///
+/// Each cycle re-reads the inputs up to `retries` more times. After a failed
+/// cycle the controller holds its last state for `hold_cycles` cycles and then
+/// moves to `safe_state` until the inputs come back.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FailSafePolicy {
+    pub retries: u32,
+    pub hold_cycles: u32,
+    pub safe_state: SafeState,
+}
+
+impl Default for FailSafePolicy {
+    fn default() -> Self {
+        FailSafePolicy {
+            retries: 2,
+            hold_cycles: 3,
+            safe_state: SafeState::AllOff,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FailSafeStatus {
+    Normal,
+    Holding { failed_cycles: u32 },
+    Engaged { failed_cycles: u32 },
+}
+
+impl FailSafePolicy {
+    pub fn status_after(&self, failed_cycles: u32) -> FailSafeStatus {
+        if failed_cycles == 0 {
+            FailSafeStatus::Normal
+        } else if failed_cycles <= self.hold_cycles {
+            FailSafeStatus::Holding { failed_cycles }
+        } else {
+            FailSafeStatus::Engaged { failed_cycles }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holds_before_engaging() {
+        let policy = FailSafePolicy {
+            retries: 0,
+            hold_cycles: 2,
+            safe_state: SafeState::AllOff,
+        };
+
+        assert!(policy.status_after(0) == FailSafeStatus::Normal);
+        assert!(policy.status_after(2) == FailSafeStatus::Holding { failed_cycles: 2 });
+        assert!(policy.status_after(3) == FailSafeStatus::Engaged { failed_cycles: 3 });
+    }
+}
@@ -1,10 +1,122 @@
-use crate::config_reader::ReadConfig;
-use crate::temperature_modifier::ModifyTemperature;
-use crate::temperature_sensor::FetchTemperature;
+use crate::clock::{Clock, SystemClock};
+use crate::config_reader::{Config, ConfigError, Mode, ReadConfig, WriteConfig};
+use crate::control_strategy::{
+    bang_bang::BangBangStrategy,
+    pid::{
+        autotune::{AutoTuneSettings, AutoTuneStatus, RelayAutoTune},
+        PidGains,
+    },
+    ActuatorCommand, ControlDecision, ControlStrategy,
+};
+use crate::setpoint_schedule::{
+    calendar::{Calendar, CalendarError, PersistCalendar, Vacation},
+    resolve_setpoint, Hold, ReadSchedule, SetpointSource, WeeklySchedule,
+};
+use crate::temperature_modifier::{
+    handle::{ActuatorHandle, OperationStatus},
+    ActuatorError, ModifyTemperature,
+};
+use crate::temperature_sensor::{FetchTemperature, SensorError};
 
+pub mod events;
+pub mod fail_safe;
+pub mod optimal_start;
+pub mod ramp;
+pub mod safety;
+pub mod short_cycle;
+pub mod staging;
+
+use events::{ControllerEvent, EventBus, TransitionReason};
+use fail_safe::{FailSafePolicy, FailSafeStatus, SafeState};
+use optimal_start::{PersistRateModel, RateModel};
+use safety::{SafetyLimit, SafetyLimits};
+use staging::{Stage, StageChange, StageSettings, StagedEquipment};
+
+#[derive(Debug)]
+pub enum ControllerError {
+    Config(ConfigError),
+    Sensor(SensorError),
+    Actuator(ActuatorError),
+    /// A zone run by a `ZoneManager` failed.
+    Zone {
+        zone: String,
+        error: Box<ControllerError>,
+    },
+    /// The zones that failed in one pass of a `ZoneManager`, each as a `Zone`
+    /// error.
+    Zones(Vec<ControllerError>),
+}
+
+impl std::fmt::Display for ControllerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ControllerError::Config(_) => write!(f, "Failed to read config"),
+            ControllerError::Sensor(_) => write!(f, "Failed to read sensor data"),
+            ControllerError::Actuator(_) => write!(f, "Failed to drive actuator"),
+            ControllerError::Zone { zone, .. } => write!(f, "Zone {zone} failed"),
+            ControllerError::Zones(errors) => {
+                write!(f, "{} zones failed: ", errors.len())?;
+                for (index, error) in errors.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match error {
+                        ControllerError::Zone { zone, .. } => write!(f, "{zone}")?,
+                        error => write!(f, "{error}")?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ControllerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ControllerError::Config(err) => Some(err),
+            ControllerError::Sensor(err) => Some(err),
+            ControllerError::Actuator(err) => Some(err),
+            ControllerError::Zone { error, .. } => Some(error.as_ref()),
+            ControllerError::Zones(_) => None,
+        }
+    }
+}
+
+impl From<ConfigError> for ControllerError {
+    fn from(err: ConfigError) -> Self {
+        ControllerError::Config(err)
+    }
+}
+
+impl From<SensorError> for ControllerError {
+    fn from(err: SensorError) -> Self {
+        ControllerError::Sensor(err)
+    }
+}
+
+impl From<ActuatorError> for ControllerError {
+    fn from(err: ActuatorError) -> Self {
+        ControllerError::Actuator(err)
+    }
+}
+
+const MAX_BUFFERED_SAMPLES: usize = 100;
+
+#[mockall::automock]
 pub trait HandleTemperature {
-    fn update_temperature(&mut self) -> Result<(), std::borrow::Cow<'static, str>>;
+    /// Reads the config and decides what to do, using the mean of the readings
+    /// sampled since the last decision or a fresh reading if there are none.
+    fn update_temperature(&mut self) -> Result<(), ControllerError>;
+    /// Takes a reading to be used by the next `update_temperature`.
+    fn sample_temperature(&mut self) -> Result<(), ControllerError>;
     fn get_current_state(&self) -> SystemState;
+    fn get_current_mode(&self) -> Mode;
+    /// The most recently read config, if any was read yet.
+    fn get_config(&self) -> Option<Config>;
+    /// The setpoint the controller is acting on, which trails the configured
+    /// one while a ramp is in progress.
+    fn get_effective_setpoint(&self) -> Option<f32>;
 }
 
 pub struct TemperatureController {
@@ -12,6 +124,39 @@ pub struct TemperatureController {
     temperature_modifier: Box<dyn ModifyTemperature>,
     config_reader: Box<dyn ReadConfig>,
     current_state: SystemState,
+    current_mode: Mode,
+    last_config: Option<Config>,
+    strategy: Box<dyn ControlStrategy>,
+    last_update: Option<std::time::Instant>,
+    short_cycle_guard: short_cycle::ShortCycleGuard,
+    deferred_transition: Option<short_cycle::DeferredTransition>,
+    event_bus: EventBus,
+    fail_safe_policy: FailSafePolicy,
+    failed_cycles: u32,
+    safety_limits: SafetyLimits,
+    /// The safety limit forcing the decision on the last cycle, if any.
+    active_safety_limit: Option<SafetyLimit>,
+    clock: std::sync::Arc<dyn Clock>,
+    samples: Vec<f32>,
+    active_operation: Option<(ActuatorCommand, ActuatorHandle)>,
+    actuator_fault: Option<(SystemState, ActuatorError)>,
+    heating_stages: StagedEquipment,
+    cooling_stages: StagedEquipment,
+    effective_setpoint: Option<f32>,
+    schedule_reader: Option<Box<dyn ReadSchedule>>,
+    schedule: Option<WeeklySchedule>,
+    hold: Option<Hold>,
+    setpoint_source: SetpointSource,
+    calendar: Calendar,
+    calendar_store: Option<Box<dyn PersistCalendar>>,
+    rate_model: RateModel,
+    rate_model_store: Option<Box<dyn PersistRateModel>>,
+    run_start: Option<(std::time::Instant, f32)>,
+    config_writer: Option<Box<dyn WriteConfig>>,
+    auto_tune: Option<RelayAutoTune>,
+    tuned_gains: Option<PidGains>,
+    /// Tuned gains not yet written to the config.
+    unsaved_gains: Option<PidGains>,
 }
 
 impl TemperatureController {
@@ -25,78 +170,851 @@ impl TemperatureController {
             temperature_modifier,
             config_reader,
             current_state: SystemState::Idle,
+            current_mode: Mode::default(),
+            last_config: None,
+            strategy: Box::new(BangBangStrategy::default()),
+            last_update: None,
+            short_cycle_guard: short_cycle::ShortCycleGuard::default(),
+            deferred_transition: None,
+            event_bus: EventBus::default(),
+            fail_safe_policy: FailSafePolicy::default(),
+            failed_cycles: 0,
+            safety_limits: SafetyLimits::default(),
+            active_safety_limit: None,
+            clock: std::sync::Arc::new(SystemClock::default()),
+            samples: Vec::new(),
+            active_operation: None,
+            actuator_fault: None,
+            heating_stages: StagedEquipment::new(SystemState::Heating),
+            cooling_stages: StagedEquipment::new(SystemState::Cooling),
+            effective_setpoint: None,
+            schedule_reader: None,
+            schedule: None,
+            hold: None,
+            setpoint_source: SetpointSource::Config,
+            calendar: Calendar::default(),
+            calendar_store: None,
+            rate_model: RateModel::default(),
+            rate_model_store: None,
+            run_start: None,
+            config_writer: None,
+            auto_tune: None,
+            tuned_gains: None,
+            unsaved_gains: None,
         }
     }
 
-    fn change_system_state(&mut self, new_state: SystemState) {
-        self.current_state = new_state;
+    pub fn with_strategy(mut self, strategy: Box<dyn ControlStrategy>) -> Self {
+        self.strategy = strategy;
+        self
     }
-}
 
-impl HandleTemperature for TemperatureController {
-    fn get_current_state(&self) -> SystemState {
-        self.current_state
+    pub fn with_fail_safe_policy(mut self, fail_safe_policy: FailSafePolicy) -> Self {
+        self.fail_safe_policy = fail_safe_policy;
+        self
+    }
+
+    pub fn with_clock(mut self, clock: std::sync::Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_safety_limits(mut self, safety_limits: SafetyLimits) -> Self {
+        self.safety_limits = safety_limits;
+        self
+    }
+
+    /// Adds a heating stage on top of the controller's own actuator. Stages
+    /// are brought in in the order they are added.
+    pub fn with_heating_stage(
+        mut self,
+        settings: StageSettings,
+        modifier: Box<dyn ModifyTemperature>,
+    ) -> Self {
+        self.heating_stages.add_stage(settings, modifier);
+        self
+    }
+
+    pub fn with_cooling_stage(
+        mut self,
+        settings: StageSettings,
+        modifier: Box<dyn ModifyTemperature>,
+    ) -> Self {
+        self.cooling_stages.add_stage(settings, modifier);
+        self
+    }
+
+    /// Takes the setpoint from a weekly schedule, re-read every cycle, instead
+    /// of the config.
+    pub fn with_schedule_reader(mut self, schedule_reader: Box<dyn ReadSchedule>) -> Self {
+        self.schedule_reader = Some(schedule_reader);
+        self
+    }
+
+    /// Vacation dates are taken in the schedule's timezone, or UTC without a
+    /// schedule, unless `calendar` has a timezone of its own.
+    pub fn with_calendar(mut self, calendar: Calendar) -> Self {
+        self.calendar = calendar;
+        self
+    }
+
+    /// Loads vacations and holidays from `store` and saves them there
+    /// whenever one is added.
+    pub fn with_calendar_store(
+        mut self,
+        store: Box<dyn PersistCalendar>,
+    ) -> Result<Self, std::io::Error> {
+        if let Some(calendar) = store.load()? {
+            self.calendar.restore(calendar);
+        }
+        self.calendar_store = Some(store);
+        Ok(self)
+    }
+
+    /// Loads the learned warm-up and cool-down rates from `store` and saves
+    /// them there whenever they change.
+    pub fn with_rate_model_store(
+        mut self,
+        store: Box<dyn PersistRateModel>,
+    ) -> Result<Self, std::io::Error> {
+        if let Some(rate_model) = store.load()? {
+            self.rate_model = rate_model;
+        }
+        self.rate_model_store = Some(store);
+        Ok(self)
+    }
+
+    pub fn get_rate_model(&self) -> RateModel {
+        self.rate_model
+    }
+
+    /// Forgets what was learned and starts again from the defaults.
+    pub fn reset_rate_model(&mut self) -> Result<(), std::io::Error> {
+        self.rate_model = RateModel::default();
+        match &self.rate_model_store {
+            Some(store) => store.save(&self.rate_model),
+            None => Ok(()),
+        }
+    }
+
+    /// Where gains found by auto-tuning are saved.
+    pub fn with_config_writer(mut self, config_writer: Box<dyn WriteConfig>) -> Self {
+        self.config_writer = Some(config_writer);
+        self
+    }
+
+    /// Hands control to a relay auto-tune until it completes or fails. The
+    /// gains it finds take precedence over the config's from then on.
+    pub fn start_auto_tune(&mut self, settings: AutoTuneSettings) {
+        self.auto_tune = Some(RelayAutoTune::new(settings));
+    }
+
+    pub fn cancel_auto_tune(&mut self) {
+        self.auto_tune = None;
+    }
+
+    /// How the last auto-tune went, if one was started.
+    pub fn get_auto_tune_status(&self) -> Option<AutoTuneStatus> {
+        self.auto_tune
+            .as_ref()
+            .map(|auto_tune| auto_tune.status().clone())
+    }
+
+    /// Adds a vacation unless it is invalid, in which case the calendar is
+    /// left as it was.
+    pub fn add_vacation(&mut self, vacation: Vacation) -> Result<(), CalendarError> {
+        self.calendar.add_vacation(vacation)?;
+        self.save_calendar()
     }
 
-    fn update_temperature(&mut self) -> Result<(), std::borrow::Cow<'static, str>> {
-        let config = match self.config_reader.get_config() {
-            Ok(result) => result,
-            Err(err) => return Err(std::format!("Failed to read config file: {err}").into()),
+    pub fn add_holiday(&mut self, date: chrono::NaiveDate) -> Result<(), CalendarError> {
+        self.calendar.add_holiday(date);
+        self.save_calendar()
+    }
+
+    fn save_calendar(&self) -> Result<(), CalendarError> {
+        match &self.calendar_store {
+            Some(store) => Ok(store.save(&self.calendar)?),
+            None => Ok(()),
+        }
+    }
+
+    /// Holds `setpoint` until the next scheduled period starts. The schedule
+    /// is read if no cycle has read it yet; without one no hold is placed.
+    pub fn hold_until_next_period(&mut self, setpoint: f32) -> Result<(), ControllerError> {
+        if self.schedule.is_none() {
+            self.read_schedule()?;
+        }
+        let until = self
+            .schedule
+            .as_ref()
+            .and_then(|schedule| schedule.next_period_start(self.clock.wall_time()))
+            .ok_or(ConfigError::Missing(None))?;
+        self.hold = Some(Hold::Temporary {
+            setpoint,
+            until: Some(until),
+        });
+        Ok(())
+    }
+
+    pub fn hold_permanently(&mut self, setpoint: f32) {
+        self.hold = Some(Hold::Permanent { setpoint });
+    }
+
+    pub fn clear_hold(&mut self) {
+        self.hold = None;
+    }
+
+    pub fn get_setpoint_source(&self) -> SetpointSource {
+        self.setpoint_source.clone()
+    }
+
+    /// The highest stage running, if the plant is heating or cooling.
+    pub fn get_current_stage(&self) -> Option<Stage> {
+        match self.current_state {
+            SystemState::Idle => None,
+            SystemState::Heating => Some(self.heating_stages.current_stage()),
+            SystemState::Cooling => Some(self.cooling_stages.current_stage()),
+        }
+    }
+
+    pub fn get_fail_safe_status(&self) -> FailSafeStatus {
+        self.fail_safe_policy.status_after(self.failed_cycles)
+    }
+
+    pub fn subscribe(&mut self, subscriber: events::Subscriber) {
+        self.event_bus.subscribe(subscriber);
+    }
+
+    /// The transition the strategy asked for on the last cycle if it was held
+    /// back to protect the equipment from short cycling.
+    pub fn get_deferred_transition(&self) -> Option<short_cycle::DeferredTransition> {
+        self.deferred_transition
+    }
+
+    /// The command the actuator is still carrying out, if any.
+    pub fn get_active_command(&self) -> Option<ActuatorCommand> {
+        self.active_operation
+            .as_ref()
+            .filter(|(_, handle)| !handle.is_finished())
+            .map(|(command, _)| *command)
+    }
+
+    /// The fault that switched the actuator off and the state it was driving,
+    /// which stays off until the fault is cleared.
+    pub fn get_actuator_fault(&self) -> Option<&(SystemState, ActuatorError)> {
+        self.actuator_fault.as_ref()
+    }
+
+    /// Lets the strategy start runs again, e.g. once the plant was repaired.
+    pub fn clear_actuator_fault(&mut self) {
+        self.actuator_fault = None;
+    }
+
+    /// Cancels whatever the actuator is doing, e.g. before shutting down.
+    pub fn stop_actuator(&mut self) {
+        if let Some((_, handle)) = self.active_operation.take() {
+            handle.cancel();
+        }
+        self.heating_stages.release_all();
+        self.cooling_stages.release_all();
+    }
+
+    /// Asks the running auto-tune for a decision, or the strategy when there
+    /// is none or it has just finished.
+    fn decide(
+        &mut self,
+        current_temperature: f32,
+        config: &Config,
+        elapsed: std::time::Duration,
+    ) -> ControlDecision {
+        if let Some(auto_tune) = self.auto_tune.as_mut() {
+            if *auto_tune.status() == AutoTuneStatus::Running {
+                let decision =
+                    auto_tune.decide(current_temperature, config, self.current_state, elapsed);
+                match auto_tune.status().clone() {
+                    AutoTuneStatus::Running => return decision,
+                    AutoTuneStatus::Completed(result) => {
+                        self.event_bus
+                            .publish(&ControllerEvent::AutoTuneCompleted { result });
+                        self.tuned_gains = Some(result.gains);
+                        self.unsaved_gains = Some(result.gains);
+                    }
+                    AutoTuneStatus::Failed(reason) => {
+                        self.event_bus
+                            .publish(&ControllerEvent::AutoTuneFailed { reason: &reason });
+                    }
+                }
+            }
+        }
+        self.save_tuned_gains();
+        let config = Config {
+            pid_gains: self.tuned_gains.or(config.pid_gains),
+            ..*config
         };
-        let config = match config {
-            Some(val) => val,
-            None => return Err("Failed to parse config".into()),
+        self.strategy
+            .decide(current_temperature, &config, self.current_state, elapsed)
+    }
+
+    /// Writes gains found by auto-tuning to the config, keeping them to try
+    /// again on the next cycle if that fails.
+    fn save_tuned_gains(&mut self) {
+        if let (Some(gains), Some(config_writer)) = (self.unsaved_gains, &self.config_writer) {
+            match config_writer.save_pid_gains(&gains) {
+                Ok(()) => self.unsaved_gains = None,
+                Err(err) => self
+                    .event_bus
+                    .publish(&ControllerEvent::TunedGainsUnsaved { error: &err }),
+            }
+        }
+    }
+
+    /// Times each heating or cooling run and, once the strategy ends it, folds
+    /// how long it took per degree into the rate model.
+    fn learn_from_run(
+        &mut self,
+        previous_state: SystemState,
+        reason: TransitionReason,
+        current_temperature: f32,
+        now: std::time::Instant,
+    ) {
+        if previous_state == self.current_state {
+            return;
+        }
+        let run_start = self.run_start.take();
+        if self.current_state != SystemState::Idle {
+            self.run_start = Some((now, current_temperature));
+        }
+        let (started, start_temperature) = match run_start {
+            Some(run_start) if reason == TransitionReason::Strategy => run_start,
+            _ => return,
         };
-        let current_temperature = match self.sensor.get_current_temperature() {
-            Some(val) => val,
-            None => return Err("Failed to read sensor data".into()),
+        let degrees = match previous_state {
+            SystemState::Cooling => start_temperature - current_temperature,
+            _ => current_temperature - start_temperature,
         };
+        if !self
+            .rate_model
+            .learn(previous_state, degrees, now.duration_since(started))
+        {
+            return;
+        }
+        if let Some(store) = &self.rate_model_store {
+            if let Err(err) = store.save(&self.rate_model) {
+                self.event_bus
+                    .publish(&ControllerEvent::PersistenceFailed { error: &err });
+            }
+        }
+    }
 
-        match self.current_state {
+    /// Reports stages whose runs failed, then brings extra stages in or lets
+    /// them go depending on how far the temperature is from the setpoint and
+    /// how long the current stage ran.
+    fn update_stages(
+        &mut self,
+        current_temperature: f32,
+        config: &Config,
+        reason: TransitionReason,
+        now: std::time::Instant,
+    ) -> Result<(), ControllerError> {
+        let mut failures = self.heating_stages.poll_failures();
+        failures.extend(self.cooling_stages.poll_failures());
+        let mut failure = None;
+        for (_, err) in failures {
+            if err.is_fault() {
+                self.event_bus
+                    .publish(&ControllerEvent::ActuatorFault { error: &err });
+            } else {
+                self.event_bus
+                    .publish(&ControllerEvent::ActuatorFailed { error: &err });
+            }
+            failure = failure.or(Some(err));
+        }
+        self.change_stages(current_temperature, config, reason, now)?;
+        match failure {
+            Some(err) => Err(err.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Stages of the other direction are stopped straight away, as are all
+    /// stages when safety or the mode forces the plant off. Only a run that
+    /// ended normally lets stages finish their minimum run time.
+    fn change_stages(
+        &mut self,
+        current_temperature: f32,
+        config: &Config,
+        reason: TransitionReason,
+        now: std::time::Instant,
+    ) -> Result<(), ControllerError> {
+        let (equipment, error, target_temperature) = match self.current_state {
             SystemState::Idle => {
-                if config.min_temperature >= current_temperature {
-                    println!("Current temperature {current_temperature} is equal or lower than minimum value of {}, raising temperature", config.min_temperature);
-                    self.change_system_state(SystemState::Heating);
-                } else if config.max_temperature <= current_temperature {
-                    println!("Current temperature {current_temperature} is equal or higher than maximum value of {}, lowering temperature", config.max_temperature);
-                    self.change_system_state(SystemState::Cooling);
+                if matches!(
+                    reason,
+                    TransitionReason::SafetyLimit | TransitionReason::ModeRestriction
+                ) {
+                    self.heating_stages.release_all();
+                    self.cooling_stages.release_all();
                 } else {
-                    println!(
-                        "Temperature {current_temperature} is within parameters {} and {}",
-                        config.min_temperature, config.max_temperature
-                    );
+                    self.heating_stages.release_due(now);
+                    self.cooling_stages.release_due(now);
                 }
+                return Ok(());
+            }
+            SystemState::Heating => {
+                self.cooling_stages.release_all();
+                (
+                    &mut self.heating_stages,
+                    config.setpoint - current_temperature,
+                    config.heating_target(),
+                )
             }
             SystemState::Cooling => {
-                if self
-                    .temperature_modifier
-                    .lower_temperature(config.max_temperature - 1.0)
-                    .is_err()
-                {
-                    eprintln!("Failed to cool temperature");
-                } else {
-                    println!("Lowered temperature");
-                    self.change_system_state(SystemState::Idle);
+                self.heating_stages.release_all();
+                (
+                    &mut self.cooling_stages,
+                    current_temperature - config.setpoint,
+                    config.cooling_target(),
+                )
+            }
+        };
+        let stage = match equipment.evaluate(error, now) {
+            None => return Ok(()),
+            Some(StageChange::Escalate) => match equipment.escalate(target_temperature, now) {
+                Ok(stage) => stage,
+                Err(err) => {
+                    self.event_bus
+                        .publish(&ControllerEvent::ActuatorFailed { error: &err });
+                    return Err(err.into());
+                }
+            },
+            Some(StageChange::DeEscalate) => equipment.de_escalate(now),
+        };
+        self.event_bus.publish(&ControllerEvent::StageChanged {
+            state: self.current_state,
+            stage,
+        });
+        Ok(())
+    }
+
+    fn change_system_state(&mut self, new_state: SystemState, reason: TransitionReason) {
+        if new_state == self.current_state {
+            return;
+        }
+        self.short_cycle_guard
+            .record_transition(self.current_state, new_state, self.clock.now());
+        self.event_bus.publish(&ControllerEvent::StateChanged {
+            from: self.current_state,
+            to: new_state,
+            reason,
+        });
+        self.current_state = new_state;
+    }
+
+    fn read_inputs(&mut self) -> Result<(Config, f32), ControllerError> {
+        let config = self.config_reader.get_config()?;
+        if self.last_config != Some(config) {
+            self.event_bus.publish(&ControllerEvent::ConfigChanged {
+                previous: self.last_config.as_ref(),
+                current: &config,
+            });
+            self.last_config = Some(config);
+        }
+        self.read_schedule()?;
+        let current_temperature = match self.take_sampled_temperature() {
+            Some(temperature) => temperature,
+            None => self.read_sensor()?,
+        };
+        Ok((config, current_temperature))
+    }
+
+    fn read_schedule(&mut self) -> Result<(), ConfigError> {
+        if let Some(schedule_reader) = &self.schedule_reader {
+            self.schedule = schedule_reader.get_schedule()?;
+            if let Some(schedule) = self.schedule.as_mut() {
+                for holiday in self.calendar.holidays() {
+                    schedule.add_holiday(*holiday);
                 }
             }
-            SystemState::Heating => {
-                if self
-                    .temperature_modifier
-                    .raise_temperature(config.min_temperature + 1.0)
-                    .is_err()
-                {
-                    eprintln!("Failed to raise temperature");
-                } else {
-                    println!("Raised temperature");
-                    self.change_system_state(SystemState::Idle);
+        }
+        Ok(())
+    }
+
+    fn read_sensor(&mut self) -> Result<f32, ControllerError> {
+        let current_temperature = match self.sensor.get_current_temperature() {
+            Ok(temperature) => temperature,
+            Err(err) => {
+                self.event_bus
+                    .publish(&ControllerEvent::SensorFailed { error: &err });
+                return Err(err.into());
+            }
+        };
+        self.event_bus.publish(&ControllerEvent::ReadingTaken {
+            temperature: current_temperature,
+        });
+        Ok(current_temperature)
+    }
+
+    fn take_sampled_temperature(&mut self) -> Option<f32> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mean = self.samples.iter().sum::<f32>() / self.samples.len() as f32;
+        self.samples.clear();
+        Some(mean)
+    }
+
+    /// Returns the decision to take instead of `decision` if a safety limit
+    /// is hit, keeping frost protection on until the temperature recovered.
+    /// Events are only published when a limit starts or stops applying.
+    fn enforce_safety_limits(
+        &mut self,
+        current_temperature: f32,
+        decision: &ControlDecision,
+    ) -> Option<ControlDecision> {
+        let frost_protecting = self.active_safety_limit == Some(SafetyLimit::Frost);
+        let enforced = self
+            .safety_limits
+            .enforce(current_temperature, frost_protecting, decision);
+        let limit = enforced.map(|(_, limit)| limit);
+        if limit != self.active_safety_limit {
+            if let Some(cleared) = self.active_safety_limit {
+                self.event_bus
+                    .publish(&ControllerEvent::SafetyLimitCleared {
+                        limit: cleared,
+                        temperature: current_temperature,
+                    });
+            }
+            if let Some(limit) = limit {
+                self.event_bus
+                    .publish(&ControllerEvent::SafetyLimitTriggered {
+                        limit,
+                        temperature: current_temperature,
+                    });
+            }
+            self.active_safety_limit = limit;
+        }
+        enforced.map(|(forced_decision, _)| forced_decision)
+    }
+
+    /// Holds the last state or moves to the safe state, still enforcing the
+    /// safety limits when `current_temperature` could be read.
+    fn handle_input_failure(&mut self, current_temperature: Option<f32>) {
+        self.failed_cycles += 1;
+        let mut decision = None;
+        let mut reason = TransitionReason::FailSafe;
+        if let FailSafeStatus::Engaged { failed_cycles } = self.get_fail_safe_status() {
+            let safe_state = self.fail_safe_policy.safe_state;
+            if failed_cycles == self.fail_safe_policy.hold_cycles + 1 {
+                self.event_bus
+                    .publish(&ControllerEvent::FailSafeEngaged { safe_state });
+            }
+            decision = Some(match safe_state {
+                SafeState::AllOff => ControlDecision::new(ActuatorCommand::Hold, SystemState::Idle),
+                SafeState::FrostProtect { temperature } => {
+                    ControlDecision::new(ActuatorCommand::Raise(temperature), SystemState::Heating)
                 }
+            });
+        }
+        if let Some(current_temperature) = current_temperature {
+            let held = decision.unwrap_or(ControlDecision::new(
+                self.get_active_command().unwrap_or(ActuatorCommand::Hold),
+                self.current_state,
+            ));
+            if let Some(forced_decision) = self.enforce_safety_limits(current_temperature, &held) {
+                decision = Some(forced_decision);
+                reason = TransitionReason::SafetyLimit;
+            }
+        }
+        if let Some(decision) = decision {
+            if decision.command == ActuatorCommand::Hold {
+                self.stop_actuator();
+            } else if self
+                .apply_command(decision.command, decision.next_state)
+                .is_err()
+            {
+                return;
+            }
+            self.change_system_state(decision.next_state, reason);
+        }
+    }
+
+    /// Starts `command` unless it is already running, cancelling any operation
+    /// it supersedes or that no longer fits `next_state`.
+    fn apply_command(
+        &mut self,
+        command: ActuatorCommand,
+        next_state: SystemState,
+    ) -> Result<(), ControllerError> {
+        if let Some((active_command, handle)) = &self.active_operation {
+            if command == *active_command && !handle.is_finished() {
+                return Ok(());
+            }
+            let superseded = command != ActuatorCommand::Hold && command != *active_command;
+            if superseded || !active_command.is_compatible_with(next_state) {
+                self.stop_actuator();
+            }
+        }
+        let handle = match command {
+            ActuatorCommand::Hold => return Ok(()),
+            ActuatorCommand::Raise(target_temperature) => {
+                self.raise_temperature(target_temperature)?
             }
+            ActuatorCommand::Lower(target_temperature) => {
+                self.lower_temperature(target_temperature)?
+            }
+        };
+        self.active_operation = Some((command, handle));
+        Ok(())
+    }
+
+    /// Forgets the active operation once it has ended and returns its command
+    /// if it ran to completion, surfacing the error of one that failed.
+    fn poll_active_operation(&mut self) -> Result<Option<ActuatorCommand>, ControllerError> {
+        let (command, status) = match &self.active_operation {
+            Some((command, handle)) => (*command, handle.poll()),
+            None => return Ok(None),
         };
+        match status {
+            OperationStatus::Running => Ok(None),
+            OperationStatus::Completed => {
+                self.active_operation = None;
+                Ok(Some(command))
+            }
+            OperationStatus::Cancelled => {
+                self.active_operation = None;
+                Ok(None)
+            }
+            OperationStatus::Failed(err) => {
+                self.active_operation = None;
+                if err.is_fault() {
+                    self.event_bus
+                        .publish(&ControllerEvent::ActuatorFault { error: &err });
+                    let faulted_state = match command {
+                        ActuatorCommand::Lower(_) => SystemState::Cooling,
+                        _ => SystemState::Heating,
+                    };
+                    self.actuator_fault = Some((faulted_state, err.clone()));
+                    self.change_system_state(SystemState::Idle, TransitionReason::ActuatorFault);
+                } else {
+                    self.event_bus
+                        .publish(&ControllerEvent::ActuatorFailed { error: &err });
+                }
+                Err(err.into())
+            }
+        }
+    }
+
+    fn raise_temperature(
+        &mut self,
+        target_temperature: f32,
+    ) -> Result<ActuatorHandle, ControllerError> {
+        self.temperature_modifier
+            .raise_temperature(target_temperature)
+            .map_err(|err| {
+                self.event_bus
+                    .publish(&ControllerEvent::ActuatorFailed { error: &err });
+                err.into()
+            })
+    }
+
+    fn lower_temperature(
+        &mut self,
+        target_temperature: f32,
+    ) -> Result<ActuatorHandle, ControllerError> {
+        self.temperature_modifier
+            .lower_temperature(target_temperature)
+            .map_err(|err| {
+                self.event_bus
+                    .publish(&ControllerEvent::ActuatorFailed { error: &err });
+                err.into()
+            })
+    }
+}
+
+impl HandleTemperature for TemperatureController {
+    fn get_current_state(&self) -> SystemState {
+        self.current_state
+    }
+
+    fn get_current_mode(&self) -> Mode {
+        self.current_mode
+    }
+
+    fn get_config(&self) -> Option<Config> {
+        self.last_config
+    }
+
+    fn get_effective_setpoint(&self) -> Option<f32> {
+        self.effective_setpoint
+    }
+
+    fn sample_temperature(&mut self) -> Result<(), ControllerError> {
+        let current_temperature = self.read_sensor()?;
+        if self.samples.len() == MAX_BUFFERED_SAMPLES {
+            self.samples.remove(0);
+        }
+        self.samples.push(current_temperature);
         Ok(())
     }
+
+    fn update_temperature(&mut self) -> Result<(), ControllerError> {
+        let mut inputs = self.read_inputs();
+        for _ in 0..self.fail_safe_policy.retries {
+            if inputs.is_ok() {
+                break;
+            }
+            inputs = self.read_inputs();
+        }
+        let (config, current_temperature) = match inputs {
+            Ok(inputs) => inputs,
+            Err(err) => {
+                // The limits do not depend on the config, so they are still
+                // checked if only the config failed.
+                let current_temperature = match err {
+                    ControllerError::Sensor(_) => None,
+                    _ => match self.take_sampled_temperature() {
+                        Some(temperature) => Some(temperature),
+                        None => self.read_sensor().ok(),
+                    },
+                };
+                self.handle_input_failure(current_temperature);
+                return Err(err);
+            }
+        };
+        if self.failed_cycles > 0 {
+            self.failed_cycles = 0;
+            self.event_bus.publish(&ControllerEvent::FailSafeRecovered);
+        }
+
+        let now = self.clock.now();
+        let elapsed = match self.last_update {
+            Some(last) => now.duration_since(last),
+            None => std::time::Duration::ZERO,
+        };
+        self.last_update = Some(now);
+
+        self.current_mode = config.mode;
+        let wall_time = self.clock.wall_time();
+        let mut config = config.with_mode_setback();
+        let (mut target_setpoint, mut setpoint_source) = resolve_setpoint(
+            config.setpoint,
+            self.schedule.as_ref(),
+            &mut self.hold,
+            wall_time,
+        );
+        if let (SetpointSource::Schedule { .. }, Some(schedule)) =
+            (&setpoint_source, self.schedule.as_ref())
+        {
+            let cool_early = config.mode == Mode::CoolOnly;
+            if let Some((setpoint, period)) = self.rate_model.early_setpoint(
+                schedule,
+                target_setpoint,
+                current_temperature,
+                cool_early,
+                wall_time,
+            ) {
+                target_setpoint = setpoint;
+                setpoint_source = SetpointSource::OptimalStart {
+                    period: period.to_string(),
+                };
+            }
+        }
+        // A vacation replaces the whole band and wins over holds and the schedule.
+        let timezone = self
+            .schedule
+            .as_ref()
+            .map_or(chrono_tz::UTC, WeeklySchedule::timezone);
+        if let Some((vacation, until)) = self.calendar.active_vacation(wall_time, timezone) {
+            config = vacation.apply(&config);
+            target_setpoint = config.setpoint;
+            setpoint_source = SetpointSource::Vacation { until };
+        }
+        if setpoint_source != self.setpoint_source {
+            self.event_bus
+                .publish(&ControllerEvent::SetpointSourceChanged {
+                    source: &setpoint_source,
+                });
+            self.setpoint_source = setpoint_source;
+        }
+        let setpoint = match self.effective_setpoint {
+            Some(effective) => ramp::ramp_towards(
+                effective,
+                target_setpoint,
+                config.setpoint_ramp_rate,
+                elapsed,
+            ),
+            None => target_setpoint,
+        };
+        self.effective_setpoint = Some(setpoint);
+        let config = Config { setpoint, ..config };
+
+        let mut decision = self.decide(current_temperature, &config, elapsed);
+        let direction = match (decision.command, decision.next_state) {
+            (ActuatorCommand::Raise(_), _) | (_, SystemState::Heating) => SystemState::Heating,
+            (ActuatorCommand::Lower(_), _) | (_, SystemState::Cooling) => SystemState::Cooling,
+            _ => SystemState::Idle,
+        };
+        let allowed_by_mode = match direction {
+            SystemState::Heating => config.mode.allows_heating(),
+            SystemState::Cooling => config.mode.allows_cooling(),
+            SystemState::Idle => true,
+        };
+        let mut transition_reason = TransitionReason::Strategy;
+        if !allowed_by_mode {
+            decision = ControlDecision::new(ActuatorCommand::Hold, SystemState::Idle);
+            transition_reason = TransitionReason::ModeRestriction;
+        } else if matches!(&self.actuator_fault, Some((faulted_state, _)) if *faulted_state == direction)
+        {
+            decision = ControlDecision::new(ActuatorCommand::Hold, SystemState::Idle);
+            transition_reason = TransitionReason::ActuatorFault;
+        }
+        self.deferred_transition = None;
+        if let Some(forced_decision) = self.enforce_safety_limits(current_temperature, &decision) {
+            decision = forced_decision;
+            transition_reason = TransitionReason::SafetyLimit;
+        } else if let Err(reason) =
+            self.short_cycle_guard
+                .check(self.current_state, decision.next_state, &config, now)
+        {
+            let deferred_transition = short_cycle::DeferredTransition {
+                from: self.current_state,
+                to: decision.next_state,
+                reason,
+            };
+            self.event_bus
+                .publish(&ControllerEvent::TransitionDeferred(deferred_transition));
+            self.deferred_transition = Some(deferred_transition);
+            // A changeover held back for anything but the run time ends the
+            // current run, so the delay counts from when it stopped.
+            decision.next_state = match (self.current_state, reason) {
+                (SystemState::Idle, _)
+                | (_, short_cycle::DeferralReason::MinimumRunTime { .. }) => self.current_state,
+                _ => SystemState::Idle,
+            };
+            if !decision.command.is_compatible_with(decision.next_state) {
+                decision.command = ActuatorCommand::Hold;
+            }
+        }
+        // A command whose run just completed has done its job and is not restarted.
+        if self.poll_active_operation()? != Some(decision.command) {
+            self.apply_command(decision.command, decision.next_state)?;
+        }
+        // A run that is still under way keeps the plant in its current state
+        // until it completes, unless the new state is the one it drives towards.
+        self.poll_active_operation()?;
+        if self.get_active_command().is_none()
+            || decision.command.is_compatible_with(decision.next_state)
+        {
+            let previous_state = self.current_state;
+            self.change_system_state(decision.next_state, transition_reason);
+            self.learn_from_run(previous_state, transition_reason, current_temperature, now);
+        }
+        self.update_stages(current_temperature, &config, transition_reason, now)
+    }
 }
 
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum SystemState {
     Idle,
     Heating,
@@ -107,7 +1025,7 @@ pub enum SystemState {
 mod tests {
     use crate::{
         config_reader::{self, Config},
-        temperature_modifier, temperature_sensor,
+        control_strategy, temperature_modifier, temperature_sensor,
     };
 
     use super::*;
@@ -119,15 +1037,12 @@ mod tests {
         let temperature_modifier_mock =
             Box::new(temperature_modifier::MockModifyTemperature::new());
 
-        config_reader_mock.expect_get_config().returning(|| {
-            Ok(Some(Config {
-                min_temperature: -5f32,
-                max_temperature: 10f32,
-            }))
-        });
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Ok(Config::from_limits(-5f32, 10f32)));
         temperature_sensor_mock
             .expect_get_current_temperature()
-            .returning(|| Some(5f32));
+            .returning(|| Ok(5f32));
 
         let mut temperature_controller: TemperatureController = TemperatureController::build(
             temperature_sensor_mock,
@@ -146,19 +1061,16 @@ mod tests {
         let mut temperature_modifier_mock =
             Box::new(temperature_modifier::MockModifyTemperature::new());
 
-        config_reader_mock.expect_get_config().returning(|| {
-            Ok(Some(Config {
-                min_temperature: -5f32,
-                max_temperature: 10f32,
-            }))
-        });
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Ok(Config::from_limits(-5f32, 10f32)));
         temperature_sensor_mock
             .expect_get_current_temperature()
-            .returning(|| Some(-7f32));
+            .returning(|| Ok(-7f32));
 
         temperature_modifier_mock
             .expect_raise_temperature()
-            .returning(|_| Ok(()));
+            .returning(|_| Ok(ActuatorHandle::completed()));
 
         let mut temperature_controller: TemperatureController = TemperatureController::build(
             temperature_sensor_mock,
@@ -186,19 +1098,16 @@ mod tests {
         let mut temperature_modifier_mock =
             Box::new(temperature_modifier::MockModifyTemperature::new());
 
-        config_reader_mock.expect_get_config().returning(|| {
-            Ok(Some(Config {
-                min_temperature: -5f32,
-                max_temperature: 10f32,
-            }))
-        });
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Ok(Config::from_limits(-5f32, 10f32)));
         temperature_sensor_mock
             .expect_get_current_temperature()
-            .returning(|| Some(15f32));
+            .returning(|| Ok(15f32));
 
         temperature_modifier_mock
             .expect_lower_temperature()
-            .returning(|_| Ok(()));
+            .returning(|_| Ok(ActuatorHandle::completed()));
 
         let mut temperature_controller: TemperatureController = TemperatureController::build(
             temperature_sensor_mock,
@@ -218,4 +1127,1469 @@ mod tests {
         let current_state = temperature_controller.get_current_state();
         assert!(current_state == expected_state);
     }
+
+    #[test]
+    fn failed_strategy_command_keeps_state() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let mut temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+        let mut strategy_mock = Box::new(control_strategy::MockControlStrategy::new());
+
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Ok(Config::from_limits(-5f32, 10f32)));
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Ok(-7f32));
+        strategy_mock.expect_decide().returning(|_, _, _, _| {
+            control_strategy::ControlDecision::new(
+                ActuatorCommand::Raise(0f32),
+                SystemState::Heating,
+            )
+        });
+        temperature_modifier_mock
+            .expect_raise_temperature()
+            .returning(|_| Err(ActuatorError::failed("heater offline")));
+
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        )
+        .with_strategy(strategy_mock);
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(matches!(
+            temperature_updated,
+            Err(ControllerError::Actuator(ActuatorError::Failed(_)))
+        ));
+        let expected_state = SystemState::Idle;
+        let current_state = temperature_controller.get_current_state();
+        assert!(current_state == expected_state);
+    }
+
+    #[test]
+    fn restart_within_minimum_off_time_is_deferred() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let mut temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        config_reader_mock.expect_get_config().returning(|| {
+            Ok(Config {
+                min_off_time: std::time::Duration::from_secs(3600),
+                ..Config::from_limits(-5f32, 10f32)
+            })
+        });
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Ok(-7f32));
+        temperature_modifier_mock
+            .expect_raise_temperature()
+            .times(1)
+            .returning(|_| Ok(ActuatorHandle::completed()));
+
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        );
+
+        for _ in 0..3 {
+            let temperature_updated = temperature_controller.update_temperature();
+            assert!(temperature_updated.is_ok());
+        }
+        let expected_state = SystemState::Idle;
+        let current_state = temperature_controller.get_current_state();
+        assert!(current_state == expected_state);
+
+        let deferred_transition = temperature_controller.get_deferred_transition();
+        assert!(deferred_transition.is_some());
+        let deferred_transition = deferred_transition.unwrap();
+        assert!(deferred_transition.to == SystemState::Heating);
+        assert!(matches!(
+            deferred_transition.reason,
+            short_cycle::DeferralReason::MinimumOffTime { .. }
+        ));
+    }
+
+    #[test]
+    fn direct_changeover_goes_through_idle() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let mut temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+        let mut strategy_mock = Box::new(control_strategy::MockControlStrategy::new());
+
+        config_reader_mock.expect_get_config().returning(|| {
+            Ok(Config {
+                min_changeover_delay: std::time::Duration::from_secs(60),
+                ..Config::from_limits(-5f32, 10f32)
+            })
+        });
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Ok(5f32));
+        let decisions = std::sync::atomic::AtomicUsize::new(0);
+        strategy_mock.expect_decide().returning(move |_, _, _, _| {
+            match decisions.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 => ControlDecision::new(ActuatorCommand::Raise(10f32), SystemState::Heating),
+                _ => ControlDecision::new(ActuatorCommand::Lower(0f32), SystemState::Cooling),
+            }
+        });
+        temperature_modifier_mock
+            .expect_raise_temperature()
+            .times(1)
+            .returning(|_| Ok(ActuatorHandle::completed()));
+        temperature_modifier_mock
+            .expect_lower_temperature()
+            .times(1)
+            .returning(|_| Ok(ActuatorHandle::completed()));
+
+        let clock = std::sync::Arc::new(crate::clock::ManualClock::default());
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        )
+        .with_strategy(strategy_mock)
+        .with_clock(clock.clone());
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_current_state() == SystemState::Heating);
+
+        clock.advance(std::time::Duration::from_secs(10));
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_current_state() == SystemState::Idle);
+
+        clock.advance(std::time::Duration::from_secs(30));
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_current_state() == SystemState::Idle);
+        assert!(
+            temperature_controller
+                .get_deferred_transition()
+                .map(|deferred| deferred.reason)
+                == Some(short_cycle::DeferralReason::ChangeoverDelay {
+                    remaining: std::time::Duration::from_secs(30)
+                })
+        );
+
+        clock.advance(std::time::Duration::from_secs(30));
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_current_state() == SystemState::Cooling);
+    }
+
+    #[test]
+    fn heat_only_mode_does_not_cool() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let mut temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        config_reader_mock.expect_get_config().returning(|| {
+            Ok(Config {
+                mode: Mode::HeatOnly,
+                ..Config::from_limits(-5f32, 10f32)
+            })
+        });
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Ok(15f32));
+        temperature_modifier_mock.expect_lower_temperature().never();
+
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        );
+
+        for _ in 0..2 {
+            let temperature_updated = temperature_controller.update_temperature();
+            assert!(temperature_updated.is_ok());
+        }
+        let expected_state = SystemState::Idle;
+        let current_state = temperature_controller.get_current_state();
+        assert!(current_state == expected_state);
+        assert!(temperature_controller.get_current_mode() == Mode::HeatOnly);
+    }
+
+    #[test]
+    fn eco_mode_widens_band() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        config_reader_mock.expect_get_config().returning(|| {
+            Ok(Config {
+                mode: Mode::Eco,
+                eco_offset: 3f32,
+                ..Config::from_limits(-5f32, 10f32)
+            })
+        });
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Ok(-7f32));
+
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        );
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        let expected_state = SystemState::Idle;
+        let current_state = temperature_controller.get_current_state();
+        assert!(current_state == expected_state);
+    }
+
+    #[test]
+    fn missing_config_is_reported() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Err(ConfigError::Missing(None)));
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Ok(5f32));
+
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        );
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(matches!(
+            temperature_updated,
+            Err(ControllerError::Config(ConfigError::Missing(_)))
+        ));
+    }
+
+    #[test]
+    fn unreachable_sensor_is_reported() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Ok(Config::from_limits(-5f32, 10f32)));
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Err(SensorError::Unreachable("connection refused".into())));
+
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        );
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(matches!(
+            temperature_updated,
+            Err(ControllerError::Sensor(SensorError::Unreachable(_)))
+        ));
+    }
+
+    #[test]
+    fn events_are_published_to_subscribers() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Ok(Config::from_limits(-5f32, 10f32)));
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Ok(-7f32));
+
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        );
+        let received = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let subscriber_received = received.clone();
+        temperature_controller.subscribe(Box::new(move |event| {
+            let name = match event {
+                ControllerEvent::ConfigChanged { previous: None, .. } => "config",
+                ControllerEvent::ReadingTaken { .. } => "reading",
+                ControllerEvent::StateChanged {
+                    from: SystemState::Idle,
+                    to: SystemState::Heating,
+                    reason: TransitionReason::Strategy,
+                } => "heating",
+                _ => "unexpected",
+            };
+            subscriber_received.borrow_mut().push(name);
+        }));
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(*received.borrow() == vec!["config", "reading", "heating"]);
+    }
+
+    #[test]
+    fn fail_safe_turns_off_after_holding() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Ok(Config::from_limits(-5f32, 10f32)));
+        let readings = std::sync::atomic::AtomicUsize::new(0);
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(
+                move || match readings.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                    0 => Ok(-7f32),
+                    _ => Err(SensorError::Unreachable("connection refused".into())),
+                },
+            );
+
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        )
+        .with_fail_safe_policy(FailSafePolicy {
+            retries: 1,
+            hold_cycles: 1,
+            safe_state: SafeState::AllOff,
+        });
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_current_state() == SystemState::Heating);
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_err());
+        assert!(temperature_controller.get_current_state() == SystemState::Heating);
+        assert!(
+            temperature_controller.get_fail_safe_status()
+                == FailSafeStatus::Holding { failed_cycles: 1 }
+        );
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_err());
+        assert!(temperature_controller.get_current_state() == SystemState::Idle);
+        assert!(
+            temperature_controller.get_fail_safe_status()
+                == FailSafeStatus::Engaged { failed_cycles: 2 }
+        );
+    }
+
+    #[test]
+    fn fail_safe_frost_protects_and_recovers() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let mut temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Ok(Config::from_limits(-5f32, 10f32)));
+        let readings = std::sync::atomic::AtomicUsize::new(0);
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(
+                move || match readings.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                    0 => Err(SensorError::Garbage("not a number".to_string())),
+                    _ => Ok(5f32),
+                },
+            );
+        temperature_modifier_mock
+            .expect_raise_temperature()
+            .withf(|target| float_cmp::approx_eq!(f32, *target, 7f32, epsilon = 0.000001))
+            .times(1)
+            .returning(|_| Ok(ActuatorHandle::completed()));
+        temperature_modifier_mock
+            .expect_raise_temperature()
+            .withf(|target| float_cmp::approx_eq!(f32, *target, -4f32, epsilon = 0.000001))
+            .times(1)
+            .returning(|_| Ok(ActuatorHandle::completed()));
+
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        )
+        .with_fail_safe_policy(FailSafePolicy {
+            retries: 0,
+            hold_cycles: 0,
+            safe_state: SafeState::FrostProtect { temperature: 7f32 },
+        });
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_err());
+        assert!(temperature_controller.get_current_state() == SystemState::Heating);
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_fail_safe_status() == FailSafeStatus::Normal);
+        assert!(temperature_controller.get_current_state() == SystemState::Idle);
+    }
+
+    #[test]
+    fn frost_limit_overrides_off_mode() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let mut temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        config_reader_mock.expect_get_config().returning(|| {
+            Ok(Config {
+                mode: Mode::Off,
+                ..Config::from_limits(-90f32, 90f32)
+            })
+        });
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Ok(3f32));
+        temperature_modifier_mock
+            .expect_raise_temperature()
+            .withf(|target| float_cmp::approx_eq!(f32, *target, 7f32, epsilon = 0.000001))
+            .times(1)
+            .returning(|_| Ok(ActuatorHandle::completed()));
+
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        )
+        .with_safety_limits(safety::SafetyLimits {
+            frost_limit: 5f32,
+            overheat_limit: 30f32,
+            frost_recovery_margin: 2f32,
+        });
+        let triggered = std::rc::Rc::new(std::cell::Cell::new(false));
+        let subscriber_triggered = triggered.clone();
+        temperature_controller.subscribe(Box::new(move |event| {
+            if let ControllerEvent::SafetyLimitTriggered {
+                limit: safety::SafetyLimit::Frost,
+                ..
+            } = event
+            {
+                subscriber_triggered.set(true);
+            }
+        }));
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_current_state() == SystemState::Heating);
+        assert!(triggered.get());
+    }
+
+    #[test]
+    fn frost_override_holds_until_recovered() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let mut temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Ok(Config::from_limits(-5f32, 10f32)));
+        let readings = std::sync::Arc::new(std::sync::Mutex::new(vec![7.5f32, 6.9, 5.5, 4.0]));
+        let sensor_readings = readings.clone();
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(move || Ok(sensor_readings.lock().unwrap().pop().unwrap()));
+        temperature_modifier_mock
+            .expect_raise_temperature()
+            .withf(|target| float_cmp::approx_eq!(f32, *target, 7f32, epsilon = 0.000001))
+            .returning(|_| Ok(ActuatorHandle::completed()));
+        // The strategy's own last run once the override let go.
+        temperature_modifier_mock
+            .expect_raise_temperature()
+            .withf(|target| *target < 0f32)
+            .times(1)
+            .returning(|_| Ok(ActuatorHandle::completed()));
+
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        )
+        .with_safety_limits(safety::SafetyLimits {
+            frost_limit: 5f32,
+            overheat_limit: 30f32,
+            frost_recovery_margin: 2f32,
+        });
+        let events = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let subscriber_events = events.clone();
+        temperature_controller.subscribe(Box::new(move |event| match event {
+            ControllerEvent::SafetyLimitTriggered { .. } => {
+                subscriber_events.borrow_mut().push("triggered")
+            }
+            ControllerEvent::SafetyLimitCleared { .. } => {
+                subscriber_events.borrow_mut().push("cleared")
+            }
+            _ => {}
+        }));
+
+        for _ in 0..3 {
+            let temperature_updated = temperature_controller.update_temperature();
+            assert!(temperature_updated.is_ok());
+            assert!(temperature_controller.get_current_state() == SystemState::Heating);
+        }
+        assert!(*events.borrow() == vec!["triggered"]);
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_current_state() == SystemState::Idle);
+        assert!(*events.borrow() == vec!["triggered", "cleared"]);
+    }
+
+    #[test]
+    fn frost_limit_applies_without_config() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let mut temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Err(ConfigError::Invalid("setpoint=hot".to_string())));
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Ok(-30f32));
+        temperature_modifier_mock
+            .expect_raise_temperature()
+            .withf(|target| float_cmp::approx_eq!(f32, *target, -18f32, epsilon = 0.000001))
+            .times(1)
+            .returning(|_| Ok(ActuatorHandle::completed()));
+
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        );
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(matches!(
+            temperature_updated,
+            Err(ControllerError::Config(ConfigError::Invalid(_)))
+        ));
+        assert!(temperature_controller.get_current_state() == SystemState::Heating);
+        assert!(
+            temperature_controller.get_fail_safe_status()
+                == FailSafeStatus::Holding { failed_cycles: 1 }
+        );
+    }
+
+    #[test]
+    fn minimum_run_time_follows_clock() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let mut temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        config_reader_mock.expect_get_config().returning(|| {
+            Ok(Config {
+                min_run_time: std::time::Duration::from_secs(300),
+                ..Config::from_limits(-5f32, 10f32)
+            })
+        });
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Ok(-7f32));
+        temperature_modifier_mock
+            .expect_raise_temperature()
+            .returning(|_| Ok(ActuatorHandle::completed()));
+
+        let clock = std::sync::Arc::new(crate::clock::ManualClock::default());
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        )
+        .with_clock(clock.clone());
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_current_state() == SystemState::Heating);
+
+        clock.advance(std::time::Duration::from_secs(100));
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_current_state() == SystemState::Heating);
+        assert!(
+            temperature_controller
+                .get_deferred_transition()
+                .map(|deferred| deferred.reason)
+                == Some(short_cycle::DeferralReason::MinimumRunTime {
+                    remaining: std::time::Duration::from_secs(200)
+                })
+        );
+
+        clock.advance(std::time::Duration::from_secs(200));
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_current_state() == SystemState::Idle);
+    }
+
+    #[test]
+    fn running_operation_is_not_restarted() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let mut temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Ok(Config::from_limits(-5f32, 10f32)));
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Ok(-7f32));
+        let (handle, progress) = ActuatorHandle::pending();
+        temperature_modifier_mock
+            .expect_raise_temperature()
+            .times(1)
+            .returning(move |_| Ok(handle.clone()));
+
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        );
+
+        for _ in 0..3 {
+            let temperature_updated = temperature_controller.update_temperature();
+            assert!(temperature_updated.is_ok());
+            assert!(temperature_controller.get_current_state() == SystemState::Heating);
+        }
+        assert!(temperature_controller.get_active_command() == Some(ActuatorCommand::Raise(-4f32)));
+
+        progress.finish(OperationStatus::Completed);
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_current_state() == SystemState::Idle);
+        assert!(temperature_controller.get_active_command().is_none());
+    }
+
+    #[test]
+    fn mode_change_cancels_running_operation() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let mut temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        let reads = std::sync::atomic::AtomicUsize::new(0);
+        config_reader_mock.expect_get_config().returning(move || {
+            let mode = match reads.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 | 1 => Mode::Auto,
+                _ => Mode::Off,
+            };
+            Ok(Config {
+                mode,
+                ..Config::from_limits(-5f32, 10f32)
+            })
+        });
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Ok(-7f32));
+        let (handle, progress) = ActuatorHandle::pending();
+        let controller_handle = handle.clone();
+        temperature_modifier_mock
+            .expect_raise_temperature()
+            .times(1)
+            .returning(move |_| Ok(controller_handle.clone()));
+
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        );
+
+        for _ in 0..2 {
+            let temperature_updated = temperature_controller.update_temperature();
+            assert!(temperature_updated.is_ok());
+            assert!(temperature_controller.get_current_state() == SystemState::Heating);
+        }
+        assert!(!progress.is_cancelled());
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(progress.is_cancelled());
+        assert!(temperature_controller.get_current_state() == SystemState::Idle);
+        assert!(temperature_controller.get_active_command().is_none());
+    }
+
+    #[test]
+    fn failed_operation_is_reported() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let mut temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Ok(Config::from_limits(-5f32, 10f32)));
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Ok(-7f32));
+        let (handle, progress) = ActuatorHandle::pending();
+        temperature_modifier_mock
+            .expect_raise_temperature()
+            .returning(move |_| Ok(handle.clone()));
+
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        );
+
+        for _ in 0..2 {
+            let temperature_updated = temperature_controller.update_temperature();
+            assert!(temperature_updated.is_ok());
+        }
+
+        progress.finish(OperationStatus::Failed(ActuatorError::failed(
+            "relay stuck",
+        )));
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(matches!(
+            temperature_updated,
+            Err(ControllerError::Actuator(ActuatorError::Failed(_)))
+        ));
+        assert!(temperature_controller.get_active_command().is_none());
+    }
+
+    #[test]
+    fn stalled_operation_raises_fault() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let mut temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Ok(Config::from_limits(-5f32, 10f32)));
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Ok(-7f32));
+        let (handle, progress) = ActuatorHandle::pending();
+        temperature_modifier_mock
+            .expect_raise_temperature()
+            .times(1)
+            .returning(move |_| Ok(handle.clone()));
+
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        );
+        let faults = std::rc::Rc::new(std::cell::Cell::new(0));
+        let subscriber_faults = faults.clone();
+        temperature_controller.subscribe(Box::new(move |event| {
+            if let ControllerEvent::ActuatorFault {
+                error: ActuatorError::Stalled { .. },
+            } = event
+            {
+                subscriber_faults.set(subscriber_faults.get() + 1);
+            }
+        }));
+
+        for _ in 0..2 {
+            let temperature_updated = temperature_controller.update_temperature();
+            assert!(temperature_updated.is_ok());
+        }
+        assert!(temperature_controller.get_current_state() == SystemState::Heating);
+
+        progress.finish(OperationStatus::Failed(ActuatorError::Stalled {
+            rate: 0f32,
+        }));
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(matches!(
+            temperature_updated,
+            Err(ControllerError::Actuator(ActuatorError::Stalled { .. }))
+        ));
+        assert!(temperature_controller.get_current_state() == SystemState::Idle);
+
+        // The heater is not tried again until the fault is cleared.
+        for _ in 0..3 {
+            let temperature_updated = temperature_controller.update_temperature();
+            assert!(temperature_updated.is_ok());
+            assert!(temperature_controller.get_current_state() == SystemState::Idle);
+        }
+        assert!(faults.get() == 1);
+        assert!(matches!(
+            temperature_controller.get_actuator_fault(),
+            Some((SystemState::Heating, ActuatorError::Stalled { .. }))
+        ));
+
+        temperature_controller.clear_actuator_fault();
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_current_state() == SystemState::Heating);
+    }
+
+    #[test]
+    fn large_error_escalates_heating_stage() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let mut temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+        let mut second_stage_mock = Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Ok(Config::from_limits(-5f32, 10f32)));
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Ok(-7f32));
+        let (handle, _progress) = ActuatorHandle::pending();
+        temperature_modifier_mock
+            .expect_raise_temperature()
+            .returning(move |_| Ok(handle.clone()));
+        second_stage_mock
+            .expect_raise_temperature()
+            .withf(|target| float_cmp::approx_eq!(f32, *target, -4f32, epsilon = 0.000001))
+            .times(1)
+            .returning(|_| Ok(ActuatorHandle::completed()));
+
+        let clock = std::sync::Arc::new(crate::clock::ManualClock::default());
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        )
+        .with_clock(clock.clone())
+        .with_heating_stage(
+            StageSettings {
+                stage: Stage::Second,
+                engage_error: 5f32,
+                engage_delay: std::time::Duration::from_secs(60),
+                release_error: 1f32,
+                min_run_time: std::time::Duration::ZERO,
+            },
+            second_stage_mock,
+        );
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_current_stage() == Some(Stage::First));
+
+        clock.advance(std::time::Duration::from_secs(60));
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_current_state() == SystemState::Heating);
+        assert!(temperature_controller.get_current_stage() == Some(Stage::Second));
+
+        temperature_controller.stop_actuator();
+        assert!(temperature_controller.get_current_stage() == Some(Stage::First));
+    }
+
+    #[test]
+    fn changeover_stops_other_direction_stages() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let mut temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+        let mut second_stage_mock = Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Ok(Config::from_limits(-5f32, 10f32)));
+        let temperature = std::sync::Arc::new(std::sync::Mutex::new(-7f32));
+        let sensor_temperature = temperature.clone();
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(move || Ok(*sensor_temperature.lock().unwrap()));
+        let (handle, progress) = ActuatorHandle::pending();
+        temperature_modifier_mock
+            .expect_raise_temperature()
+            .returning(move |_| Ok(handle.clone()));
+        temperature_modifier_mock
+            .expect_lower_temperature()
+            .returning(|_| Ok(ActuatorHandle::completed()));
+        let (stage_handle, stage_progress) = ActuatorHandle::pending();
+        second_stage_mock
+            .expect_raise_temperature()
+            .times(1)
+            .returning(move |_| Ok(stage_handle.clone()));
+
+        let clock = std::sync::Arc::new(crate::clock::ManualClock::default());
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        )
+        .with_clock(clock.clone())
+        .with_heating_stage(
+            StageSettings {
+                stage: Stage::Second,
+                engage_error: 5f32,
+                engage_delay: std::time::Duration::from_secs(60),
+                release_error: 1f32,
+                min_run_time: std::time::Duration::from_secs(3600),
+            },
+            second_stage_mock,
+        );
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        clock.advance(std::time::Duration::from_secs(60));
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_current_stage() == Some(Stage::Second));
+
+        *temperature.lock().unwrap() = 20f32;
+        progress.finish(OperationStatus::Completed);
+        for _ in 0..2 {
+            clock.advance(std::time::Duration::from_secs(1));
+            let temperature_updated = temperature_controller.update_temperature();
+            assert!(temperature_updated.is_ok());
+        }
+        assert!(temperature_controller.get_current_state() == SystemState::Cooling);
+        assert!(stage_progress.is_cancelled());
+    }
+
+    #[test]
+    fn setpoint_change_is_ramped() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        let reads = std::sync::atomic::AtomicUsize::new(0);
+        config_reader_mock.expect_get_config().returning(move || {
+            let setpoint = match reads.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 => 20f32,
+                _ => 26f32,
+            };
+            Ok(Config {
+                setpoint,
+                setpoint_ramp_rate: Some(1f32),
+                ..Config::default()
+            })
+        });
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Ok(20.5f32));
+
+        let clock = std::sync::Arc::new(crate::clock::ManualClock::default());
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        )
+        .with_clock(clock.clone());
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_effective_setpoint() == Some(20f32));
+
+        clock.advance(std::time::Duration::from_secs(120));
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        let effective_setpoint = temperature_controller.get_effective_setpoint().unwrap();
+        assert!(float_cmp::approx_eq!(
+            f32,
+            effective_setpoint,
+            22f32,
+            epsilon = 0.000001
+        ));
+        assert!(
+            temperature_controller
+                .get_config()
+                .map(|config| config.setpoint)
+                == Some(26f32)
+        );
+        assert!(temperature_controller.get_current_state() == SystemState::Heating);
+    }
+
+    #[test]
+    fn schedule_and_holds_pick_setpoint() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+        let mut schedule_reader_mock = Box::new(crate::setpoint_schedule::MockReadSchedule::new());
+
+        config_reader_mock.expect_get_config().returning(|| {
+            Ok(Config {
+                setpoint: 20f32,
+                heating_deadband: 10f32,
+                cooling_deadband: 10f32,
+                ..Config::default()
+            })
+        });
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Ok(19f32));
+        schedule_reader_mock.expect_get_schedule().returning(|| {
+            crate::setpoint_schedule::parse_schedule_lines([
+                "timezone=Europe/London",
+                "daily 06:30 wake 21",
+                "daily 22:00 sleep 17",
+            ])
+            .map(Some)
+        });
+
+        // Monday 2024-01-08 07:00 in London.
+        let clock = std::sync::Arc::new(crate::clock::ManualClock::starting_at(
+            chrono::DateTime::parse_from_rfc3339("2024-01-08T07:00:00Z")
+                .unwrap()
+                .with_timezone(&chrono::Utc),
+        ));
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        )
+        .with_clock(clock.clone())
+        .with_schedule_reader(schedule_reader_mock);
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_effective_setpoint() == Some(21f32));
+        assert!(
+            temperature_controller.get_setpoint_source()
+                == SetpointSource::Schedule {
+                    period: "wake".to_string()
+                }
+        );
+
+        let held = temperature_controller.hold_until_next_period(18f32);
+        assert!(held.is_ok());
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_effective_setpoint() == Some(18f32));
+
+        clock.advance(std::time::Duration::from_secs(15 * 3600));
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_effective_setpoint() == Some(17f32));
+
+        temperature_controller.hold_permanently(22f32);
+        clock.advance(std::time::Duration::from_secs(24 * 3600));
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_effective_setpoint() == Some(22f32));
+    }
+
+    #[test]
+    fn hold_before_first_cycle_reads_schedule() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+        let mut schedule_reader_mock = Box::new(crate::setpoint_schedule::MockReadSchedule::new());
+
+        config_reader_mock.expect_get_config().returning(|| {
+            Ok(Config {
+                setpoint: 20f32,
+                heating_deadband: 10f32,
+                cooling_deadband: 10f32,
+                ..Config::default()
+            })
+        });
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Ok(19f32));
+        schedule_reader_mock.expect_get_schedule().returning(|| {
+            crate::setpoint_schedule::parse_schedule_lines([
+                "timezone=Europe/London",
+                "daily 06:30 wake 21",
+                "daily 22:00 sleep 17",
+            ])
+            .map(Some)
+        });
+
+        // Monday 2024-01-08 07:00 in London.
+        let clock = std::sync::Arc::new(crate::clock::ManualClock::starting_at(
+            chrono::DateTime::parse_from_rfc3339("2024-01-08T07:00:00Z")
+                .unwrap()
+                .with_timezone(&chrono::Utc),
+        ));
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        )
+        .with_clock(clock.clone())
+        .with_schedule_reader(schedule_reader_mock);
+
+        let held = temperature_controller.hold_until_next_period(18f32);
+        assert!(held.is_ok());
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_effective_setpoint() == Some(18f32));
+
+        clock.advance(std::time::Duration::from_secs(15 * 3600));
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_effective_setpoint() == Some(17f32));
+    }
+
+    #[test]
+    fn hold_until_next_period_needs_schedule() {
+        let config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        );
+
+        let held = temperature_controller.hold_until_next_period(18f32);
+        assert!(matches!(
+            held,
+            Err(ControllerError::Config(ConfigError::Missing(None)))
+        ));
+    }
+
+    #[test]
+    fn vacation_band_applies_until_pre_heat() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let mut temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Ok(Config::default()));
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Ok(15f32));
+        temperature_modifier_mock
+            .expect_raise_temperature()
+            .returning(|_| Ok(ActuatorHandle::completed()));
+
+        let clock = std::sync::Arc::new(crate::clock::ManualClock::starting_at(
+            chrono::DateTime::parse_from_rfc3339("2024-07-14T12:00:00Z")
+                .unwrap()
+                .with_timezone(&chrono::Utc),
+        ));
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        )
+        .with_clock(clock.clone());
+        let added = temperature_controller.add_vacation(Vacation {
+            start: chrono::NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+            end: chrono::NaiveDate::from_ymd_opt(2024, 7, 15).unwrap(),
+            min_temperature: 12f32,
+            max_temperature: 28f32,
+            pre_heat: std::time::Duration::from_secs(6 * 3600),
+        });
+        assert!(added.is_ok());
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_effective_setpoint() == Some(20f32));
+        assert!(temperature_controller.get_current_state() == SystemState::Idle);
+        assert!(matches!(
+            temperature_controller.get_setpoint_source(),
+            SetpointSource::Vacation { .. }
+        ));
+
+        clock.advance(std::time::Duration::from_secs(6 * 3600));
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_effective_setpoint() == Some(21f32));
+        assert!(temperature_controller.get_setpoint_source() == SetpointSource::Config);
+        assert!(temperature_controller.get_current_state() == SystemState::Heating);
+    }
+
+    #[test]
+    fn stored_vacation_follows_schedule_timezone() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+        let mut schedule_reader_mock = Box::new(crate::setpoint_schedule::MockReadSchedule::new());
+        let mut calendar_store_mock =
+            Box::new(crate::setpoint_schedule::calendar::MockPersistCalendar::new());
+
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Ok(Config::default()));
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(|| Ok(18f32));
+        schedule_reader_mock.expect_get_schedule().returning(|| {
+            crate::setpoint_schedule::parse_schedule_lines(["timezone=Europe/Berlin"]).map(Some)
+        });
+        calendar_store_mock.expect_load().returning(|| {
+            let mut calendar = Calendar::default();
+            let added = calendar.add_vacation(Vacation {
+                start: chrono::NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+                end: chrono::NaiveDate::from_ymd_opt(2024, 7, 15).unwrap(),
+                min_temperature: 12f32,
+                max_temperature: 28f32,
+                pre_heat: std::time::Duration::ZERO,
+            });
+            assert!(added.is_ok());
+            Ok(Some(calendar))
+        });
+        calendar_store_mock
+            .expect_save()
+            .times(1)
+            .returning(|_| Ok(()));
+
+        // Already 1 July in Berlin, still 30 June in UTC.
+        let clock = std::sync::Arc::new(crate::clock::ManualClock::starting_at(
+            chrono::DateTime::parse_from_rfc3339("2024-06-30T22:30:00Z")
+                .unwrap()
+                .with_timezone(&chrono::Utc),
+        ));
+        let temperature_controller = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        )
+        .with_clock(clock.clone())
+        .with_schedule_reader(schedule_reader_mock)
+        .with_calendar_store(calendar_store_mock);
+        assert!(temperature_controller.is_ok());
+        let mut temperature_controller = temperature_controller.unwrap();
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(matches!(
+            temperature_controller.get_setpoint_source(),
+            SetpointSource::Vacation { .. }
+        ));
+        let added = temperature_controller
+            .add_holiday(chrono::NaiveDate::from_ymd_opt(2024, 12, 25).unwrap());
+        assert!(added.is_ok());
+    }
+
+    #[test]
+    fn optimal_start_uses_learned_rate() {
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_sensor_mock = Box::new(temperature_sensor::MockFetchTemperature::new());
+        let mut temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+        let mut schedule_reader_mock = Box::new(crate::setpoint_schedule::MockReadSchedule::new());
+        let mut rate_model_store_mock = Box::new(optimal_start::MockPersistRateModel::new());
+
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Ok(Config::default()));
+        let temperature = std::sync::Arc::new(std::sync::Mutex::new(17f32));
+        let sensor_temperature = temperature.clone();
+        temperature_sensor_mock
+            .expect_get_current_temperature()
+            .returning(move || Ok(*sensor_temperature.lock().unwrap()));
+        temperature_modifier_mock
+            .expect_raise_temperature()
+            .returning(|_| Ok(ActuatorHandle::completed()));
+        schedule_reader_mock.expect_get_schedule().returning(|| {
+            crate::setpoint_schedule::parse_schedule_lines([
+                "timezone=UTC",
+                "daily 06:00 wake 21",
+                "daily 22:00 sleep 17",
+            ])
+            .map(Some)
+        });
+        rate_model_store_mock.expect_load().returning(|| {
+            Ok(Some(RateModel {
+                heating_minutes_per_degree: 20.0,
+                heating_runs: 4,
+                ..RateModel::default()
+            }))
+        });
+        rate_model_store_mock
+            .expect_save()
+            .withf(|model| model.heating_runs == 5)
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let clock = std::sync::Arc::new(crate::clock::ManualClock::starting_at(
+            chrono::DateTime::parse_from_rfc3339("2024-01-08T04:00:00Z")
+                .unwrap()
+                .with_timezone(&chrono::Utc),
+        ));
+        let temperature_controller = TemperatureController::build(
+            temperature_sensor_mock,
+            temperature_modifier_mock,
+            config_reader_mock,
+        )
+        .with_clock(clock.clone())
+        .with_schedule_reader(schedule_reader_mock)
+        .with_rate_model_store(rate_model_store_mock);
+        assert!(temperature_controller.is_ok());
+        let mut temperature_controller = temperature_controller.unwrap();
+
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_effective_setpoint() == Some(17f32));
+
+        // Four degrees at 20 minutes each means starting at 04:40.
+        clock.advance(std::time::Duration::from_secs(40 * 60));
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_effective_setpoint() == Some(21f32));
+        assert!(
+            temperature_controller.get_setpoint_source()
+                == SetpointSource::OptimalStart {
+                    period: "wake".to_string()
+                }
+        );
+        assert!(temperature_controller.get_current_state() == SystemState::Heating);
+
+        clock.advance(std::time::Duration::from_secs(60 * 60));
+        *temperature.lock().unwrap() = 22f32;
+        let temperature_updated = temperature_controller.update_temperature();
+        assert!(temperature_updated.is_ok());
+        assert!(temperature_controller.get_current_state() == SystemState::Idle);
+        assert!(temperature_controller.get_rate_model().heating_runs == 5);
+    }
+
+    /// Runs an auto-tune against a simulated plant until it stops running.
+    /// The caller holds the simulation lock.
+    fn auto_tune_simulated_plant(
+        config_writer_mock: Box<config_reader::MockWriteConfig>,
+    ) -> TemperatureController {
+        use crate::control_strategy::pid::autotune::{AutoTuneSettings, AutoTuneStatus};
+        use crate::temperature_value_provider::{SimulatedPlant, TemperatureValueProvider};
+
+        TemperatureValueProvider::set_current_temperature(18f32);
+        const STEP: std::time::Duration = std::time::Duration::from_secs(10);
+        let mut plant = SimulatedPlant::new(
+            15f32,
+            10f32,
+            std::time::Duration::from_secs(1200),
+            std::time::Duration::from_secs(120),
+            STEP,
+        );
+
+        let mut config_reader_mock = Box::new(config_reader::MockReadConfig::new());
+        let mut temperature_modifier_mock =
+            Box::new(temperature_modifier::MockModifyTemperature::new());
+
+        config_reader_mock
+            .expect_get_config()
+            .returning(|| Ok(Config::default()));
+        let output = std::sync::Arc::new(std::sync::Mutex::new(0f32));
+        let raise_output = output.clone();
+        temperature_modifier_mock
+            .expect_raise_temperature()
+            .returning(move |_| {
+                *raise_output.lock().unwrap() = 1.0;
+                Ok(ActuatorHandle::completed())
+            });
+        let lower_output = output.clone();
+        temperature_modifier_mock
+            .expect_lower_temperature()
+            .returning(move |_| {
+                *lower_output.lock().unwrap() = -1.0;
+                Ok(ActuatorHandle::completed())
+            });
+
+        let clock = std::sync::Arc::new(crate::clock::ManualClock::default());
+        let mut temperature_controller: TemperatureController = TemperatureController::build(
+            Box::new(temperature_sensor::serial::TemperatureSensorSerial {}),
+            temperature_modifier_mock,
+            config_reader_mock,
+        )
+        .with_clock(clock.clone())
+        .with_config_writer(config_writer_mock);
+        temperature_controller.start_auto_tune(AutoTuneSettings::default());
+
+        for _ in 0..2000 {
+            if temperature_controller.get_auto_tune_status() != Some(AutoTuneStatus::Running) {
+                break;
+            }
+            let temperature_updated = temperature_controller.update_temperature();
+            assert!(temperature_updated.is_ok());
+            plant.advance(*output.lock().unwrap());
+            clock.advance(STEP);
+        }
+        temperature_controller
+    }
+
+    #[test]
+    fn auto_tune_against_simulated_plant() {
+        use crate::control_strategy::pid::autotune::AutoTuneStatus;
+        use crate::temperature_value_provider::TemperatureValueProvider;
+
+        let _simulation = TemperatureValueProvider::lock_for_test();
+        let mut config_writer_mock = Box::new(config_reader::MockWriteConfig::new());
+        config_writer_mock
+            .expect_save_pid_gains()
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let temperature_controller = auto_tune_simulated_plant(config_writer_mock);
+
+        let result = match temperature_controller.get_auto_tune_status() {
+            Some(AutoTuneStatus::Completed(result)) => result,
+            status => panic!("auto-tune did not complete: {status:?}"),
+        };
+        // A dead time of two minutes on a twenty minute lag oscillates with a
+        // period of a few times the dead time.
+        assert!(result.ultimate_period > std::time::Duration::from_secs(240));
+        assert!(result.ultimate_period < std::time::Duration::from_secs(1200));
+        assert!(result.ultimate_gain > 0.0);
+        assert!(result.gains.kp > 0.0 && result.gains.ki > 0.0 && result.gains.kd > 0.0);
+    }
+
+    #[test]
+    fn unsaved_tuned_gains_are_retried() {
+        use crate::control_strategy::pid::autotune::AutoTuneStatus;
+        use crate::temperature_value_provider::TemperatureValueProvider;
+
+        let _simulation = TemperatureValueProvider::lock_for_test();
+        let mut config_writer_mock = Box::new(config_reader::MockWriteConfig::new());
+        let mut sequence = mockall::Sequence::new();
+        // The first attempt is made on the cycle the auto-tune completes.
+        config_writer_mock
+            .expect_save_pid_gains()
+            .times(2)
+            .in_sequence(&mut sequence)
+            .returning(|_| {
+                Err(ConfigError::Unavailable(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    "read-only",
+                ))))
+            });
+        config_writer_mock
+            .expect_save_pid_gains()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_| Ok(()));
+
+        let mut temperature_controller = auto_tune_simulated_plant(config_writer_mock);
+        assert!(matches!(
+            temperature_controller.get_auto_tune_status(),
+            Some(AutoTuneStatus::Completed(_))
+        ));
+        let unsaved = std::rc::Rc::new(std::cell::Cell::new(0));
+        let subscriber_unsaved = unsaved.clone();
+        temperature_controller.subscribe(Box::new(move |event| {
+            if let ControllerEvent::TunedGainsUnsaved { .. } = event {
+                subscriber_unsaved.set(subscriber_unsaved.get() + 1);
+            }
+        }));
+
+        for _ in 0..3 {
+            let temperature_updated = temperature_controller.update_temperature();
+            assert!(temperature_updated.is_ok());
+        }
+        assert!(unsaved.get() == 1);
+    }
 }
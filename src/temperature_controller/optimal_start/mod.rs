@@ -0,0 +1,203 @@
+use crate::json_file::JsonFile;
+use crate::setpoint_schedule::WeeklySchedule;
+use crate::temperature_controller::SystemState;
+
+pub const DEFAULT_MINUTES_PER_DEGREE: f32 = 15.0;
+/// Weight of the newest run in the learned rate.
+pub const LEARNING_RATE: f32 = 0.3;
+/// Runs shorter than this say more about sensor noise than about the room.
+pub const MIN_LEARNED_DEGREES: f32 = 0.5;
+pub const MAX_LEAD_TIME: std::time::Duration = std::time::Duration::from_secs(4 * 3600);
+
+/// How long the room takes to warm up or cool down, learned from completed
+/// runs.
+#[derive(Clone, Copy, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+pub struct RateModel {
+    pub heating_minutes_per_degree: f32,
+    pub cooling_minutes_per_degree: f32,
+    pub heating_runs: u32,
+    pub cooling_runs: u32,
+}
+
+impl Default for RateModel {
+    fn default() -> Self {
+        RateModel {
+            heating_minutes_per_degree: DEFAULT_MINUTES_PER_DEGREE,
+            cooling_minutes_per_degree: DEFAULT_MINUTES_PER_DEGREE,
+            heating_runs: 0,
+            cooling_runs: 0,
+        }
+    }
+}
+
+impl RateModel {
+    /// Folds a finished run into the model. The first run replaces the
+    /// default, later ones are blended in. Returns whether the run was used.
+    pub fn learn(
+        &mut self,
+        state: SystemState,
+        degrees: f32,
+        duration: std::time::Duration,
+    ) -> bool {
+        if degrees < MIN_LEARNED_DEGREES {
+            return false;
+        }
+        let observed = duration.as_secs_f32() / 60.0 / degrees;
+        let (rate, runs) = match state {
+            SystemState::Heating => (&mut self.heating_minutes_per_degree, &mut self.heating_runs),
+            SystemState::Cooling => (&mut self.cooling_minutes_per_degree, &mut self.cooling_runs),
+            SystemState::Idle => return false,
+        };
+        *rate = match *runs {
+            0 => observed,
+            _ => *rate + LEARNING_RATE * (observed - *rate),
+        };
+        *runs += 1;
+        true
+    }
+
+    /// How long before the target time a run of `degrees` must start.
+    pub fn lead_time(&self, state: SystemState, degrees: f32) -> std::time::Duration {
+        let minutes_per_degree = match state {
+            SystemState::Heating => self.heating_minutes_per_degree,
+            SystemState::Cooling => self.cooling_minutes_per_degree,
+            SystemState::Idle => return std::time::Duration::ZERO,
+        };
+        std::time::Duration::try_from_secs_f32(degrees.max(0.0) * minutes_per_degree * 60.0)
+            .unwrap_or(MAX_LEAD_TIME)
+            .min(MAX_LEAD_TIME)
+    }
+
+    /// The next period's setpoint if it asks for more than `current_setpoint`
+    /// and it is time to start working towards it, along with the period's
+    /// name. A lower setpoint is only worked towards early when
+    /// `cool_early` is set, since it is usually a setback rather than a
+    /// request for cooling.
+    pub fn early_setpoint<'a>(
+        &self,
+        schedule: &'a WeeklySchedule,
+        current_setpoint: f32,
+        current_temperature: f32,
+        cool_early: bool,
+        at: chrono::DateTime<chrono::Utc>,
+    ) -> Option<(f32, &'a str)> {
+        let (period, starts) = schedule.next_period(at)?;
+        let (state, degrees) = if period.setpoint > current_setpoint {
+            (SystemState::Heating, period.setpoint - current_temperature)
+        } else if period.setpoint < current_setpoint && cool_early {
+            (SystemState::Cooling, current_temperature - period.setpoint)
+        } else {
+            return None;
+        };
+        let lead_time = self.lead_time(state, degrees);
+        (at + lead_time >= starts).then_some((period.setpoint, period.name.as_str()))
+    }
+}
+
+/// Keeps the learned model across restarts.
+#[mockall::automock]
+pub trait PersistRateModel {
+    fn load(&self) -> Result<Option<RateModel>, std::io::Error>;
+    fn save(&self, model: &RateModel) -> Result<(), std::io::Error>;
+}
+
+pub type RateModelFile = JsonFile<RateModel>;
+
+impl PersistRateModel for RateModelFile {
+    fn load(&self) -> Result<Option<RateModel>, std::io::Error> {
+        self.read()
+    }
+
+    fn save(&self, model: &RateModel) -> Result<(), std::io::Error> {
+        self.write(model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(value: &str) -> chrono::DateTime<chrono::Utc> {
+        chrono::DateTime::parse_from_rfc3339(value)
+            .unwrap()
+            .with_timezone(&chrono::Utc)
+    }
+
+    #[test]
+    fn learns_from_runs() {
+        let mut model = RateModel::default();
+
+        assert!(model.learn(
+            SystemState::Heating,
+            2.0,
+            std::time::Duration::from_secs(1200)
+        ));
+        assert!(float_cmp::approx_eq!(
+            f32,
+            model.heating_minutes_per_degree,
+            10f32,
+            epsilon = 0.0001
+        ));
+
+        assert!(model.learn(
+            SystemState::Heating,
+            1.0,
+            std::time::Duration::from_secs(1200)
+        ));
+        assert!(float_cmp::approx_eq!(
+            f32,
+            model.heating_minutes_per_degree,
+            13f32,
+            epsilon = 0.0001
+        ));
+        assert!(model.heating_runs == 2);
+        assert!(model.cooling_runs == 0);
+
+        assert!(!model.learn(
+            SystemState::Cooling,
+            0.1,
+            std::time::Duration::from_secs(60)
+        ));
+    }
+
+    #[test]
+    fn starts_early_for_next_period() {
+        let schedule = crate::setpoint_schedule::parse_schedule_lines([
+            "timezone=UTC",
+            "daily 06:00 wake 21",
+            "daily 22:00 sleep 17",
+        ])
+        .unwrap();
+        let model = RateModel {
+            heating_minutes_per_degree: 20.0,
+            heating_runs: 1,
+            ..RateModel::default()
+        };
+
+        // Four degrees to go takes 80 minutes, so heating starts at 04:40.
+        let early =
+            model.early_setpoint(&schedule, 17f32, 17f32, false, utc("2024-01-08T04:39:00Z"));
+        assert!(early.is_none());
+        let early =
+            model.early_setpoint(&schedule, 17f32, 17f32, false, utc("2024-01-08T04:40:00Z"));
+        assert!(early == Some((21f32, "wake")));
+    }
+
+    #[test]
+    fn setback_is_not_started_early() {
+        let schedule = crate::setpoint_schedule::parse_schedule_lines([
+            "timezone=UTC",
+            "daily 06:00 wake 21",
+            "daily 22:00 sleep 17",
+        ])
+        .unwrap();
+        let model = RateModel::default();
+
+        let early =
+            model.early_setpoint(&schedule, 21f32, 21f32, false, utc("2024-01-08T21:59:00Z"));
+        assert!(early.is_none());
+        let early =
+            model.early_setpoint(&schedule, 21f32, 21f32, true, utc("2024-01-08T21:59:00Z"));
+        assert!(early == Some((17f32, "sleep")));
+    }
+}
@@ -0,0 +1,66 @@
+/// Moves `current` towards `target` by at most `rate` degrees per minute over
+/// `elapsed`. A missing or non-positive rate jumps straight to the target.
+pub fn ramp_towards(
+    current: f32,
+    target: f32,
+    rate: Option<f32>,
+    elapsed: std::time::Duration,
+) -> f32 {
+    let rate = match rate {
+        Some(rate) if rate > 0.0 => rate,
+        _ => return target,
+    };
+    let max_step = rate * elapsed.as_secs_f32() / 60.0;
+    current + (target - current).clamp(-max_step, max_step)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_at_rate() {
+        let effective = ramp_towards(20f32, 24f32, Some(0.5), std::time::Duration::from_secs(120));
+        assert!(float_cmp::approx_eq!(
+            f32,
+            effective,
+            21f32,
+            epsilon = 0.000001
+        ));
+
+        let effective = ramp_towards(24f32, 20f32, Some(0.5), std::time::Duration::from_secs(60));
+        assert!(float_cmp::approx_eq!(
+            f32,
+            effective,
+            23.5f32,
+            epsilon = 0.000001
+        ));
+    }
+
+    #[test]
+    fn stops_at_target() {
+        let effective = ramp_towards(
+            20f32,
+            20.2f32,
+            Some(1.0),
+            std::time::Duration::from_secs(60),
+        );
+        assert!(float_cmp::approx_eq!(
+            f32,
+            effective,
+            20.2f32,
+            epsilon = 0.000001
+        ));
+    }
+
+    #[test]
+    fn no_rate_jumps() {
+        let effective = ramp_towards(20f32, 24f32, None, std::time::Duration::ZERO);
+        assert!(float_cmp::approx_eq!(
+            f32,
+            effective,
+            24f32,
+            epsilon = 0.000001
+        ));
+    }
+}
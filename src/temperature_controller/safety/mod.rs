@@ -0,0 +1,129 @@
+use crate::control_strategy::{ActuatorCommand, ControlDecision};
+use crate::temperature_controller::SystemState;
+
+/// Compiled defaults. They sit outside any band the controller is expected to
+/// run in, so they only act when the user config is badly wrong.
+pub const DEFAULT_FROST_LIMIT: f32 = -20.0;
+pub const DEFAULT_OVERHEAT_LIMIT: f32 = 40.0;
+pub const DEFAULT_FROST_RECOVERY_MARGIN: f32 = 2.0;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SafetyLimit {
+    Frost,
+    Overheat,
+}
+
+/// Absolute limits enforced on every cycle regardless of mode, strategy or
+/// short-cycle protection. They are set on the controller, never read from
+/// `ReadConfig`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SafetyLimits {
+    pub frost_limit: f32,
+    pub overheat_limit: f32,
+    /// How far above the frost limit forced heating drives the temperature
+    /// before it lets go.
+    pub frost_recovery_margin: f32,
+}
+
+impl Default for SafetyLimits {
+    fn default() -> Self {
+        SafetyLimits {
+            frost_limit: DEFAULT_FROST_LIMIT,
+            overheat_limit: DEFAULT_OVERHEAT_LIMIT,
+            frost_recovery_margin: DEFAULT_FROST_RECOVERY_MARGIN,
+        }
+    }
+}
+
+impl SafetyLimits {
+    /// Returns the decision to take instead of `decision` when a limit is hit.
+    /// `frost_protecting` tells whether the frost limit was hit on the last
+    /// cycle, in which case heating is kept on until the recovery margin is
+    /// reached.
+    pub fn enforce(
+        &self,
+        current_temperature: f32,
+        frost_protecting: bool,
+        decision: &ControlDecision,
+    ) -> Option<(ControlDecision, SafetyLimit)> {
+        let recovered = current_temperature >= self.frost_limit + self.frost_recovery_margin;
+        if current_temperature <= self.frost_limit || (frost_protecting && !recovered) {
+            let forced = ControlDecision::new(
+                ActuatorCommand::Raise(self.frost_limit + self.frost_recovery_margin),
+                SystemState::Heating,
+            );
+            return Some((forced, SafetyLimit::Frost));
+        }
+
+        let heating = matches!(decision.command, ActuatorCommand::Raise(_))
+            || decision.next_state == SystemState::Heating;
+        if current_temperature >= self.overheat_limit && heating {
+            let forced = ControlDecision::new(ActuatorCommand::Hold, SystemState::Idle);
+            return Some((forced, SafetyLimit::Overheat));
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: SafetyLimits = SafetyLimits {
+        frost_limit: 5.0,
+        overheat_limit: 30.0,
+        frost_recovery_margin: 2.0,
+    };
+
+    #[test]
+    fn forces_heating_below_frost_limit() {
+        let decision = ControlDecision::new(ActuatorCommand::Hold, SystemState::Idle);
+
+        let enforced = LIMITS.enforce(4.0, false, &decision);
+
+        assert!(
+            enforced
+                == Some((
+                    ControlDecision::new(ActuatorCommand::Raise(7.0), SystemState::Heating),
+                    SafetyLimit::Frost
+                ))
+        );
+    }
+
+    #[test]
+    fn keeps_heating_until_recovered() {
+        let decision = ControlDecision::new(ActuatorCommand::Hold, SystemState::Idle);
+
+        assert!(LIMITS.enforce(6.0, false, &decision).is_none());
+        assert!(matches!(
+            LIMITS.enforce(6.0, true, &decision),
+            Some((_, SafetyLimit::Frost))
+        ));
+        assert!(LIMITS.enforce(7.0, true, &decision).is_none());
+    }
+
+    #[test]
+    fn cuts_heating_above_overheat_limit() {
+        let decision = ControlDecision::new(ActuatorCommand::Raise(35.0), SystemState::Heating);
+
+        let enforced = LIMITS.enforce(31.0, false, &decision);
+
+        assert!(
+            enforced
+                == Some((
+                    ControlDecision::new(ActuatorCommand::Hold, SystemState::Idle),
+                    SafetyLimit::Overheat
+                ))
+        );
+    }
+
+    #[test]
+    fn leaves_cooling_alone_above_overheat_limit() {
+        let decision = ControlDecision::new(ActuatorCommand::Lower(25.0), SystemState::Cooling);
+
+        let enforced = LIMITS.enforce(31.0, false, &decision);
+
+        assert!(enforced.is_none());
+    }
+}
@@ -0,0 +1,228 @@
+use crate::config_reader::Config;
+use crate::temperature_controller::SystemState;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DeferralReason {
+    MinimumRunTime { remaining: std::time::Duration },
+    MinimumOffTime { remaining: std::time::Duration },
+    ChangeoverDelay { remaining: std::time::Duration },
+}
+
+impl std::fmt::Display for DeferralReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeferralReason::MinimumRunTime { remaining } => {
+                write!(f, "minimum run time not reached, {remaining:?} remaining")
+            }
+            DeferralReason::MinimumOffTime { remaining } => {
+                write!(f, "minimum off time not reached, {remaining:?} remaining")
+            }
+            DeferralReason::ChangeoverDelay { remaining } => {
+                write!(
+                    f,
+                    "heat/cool changeover delay not reached, {remaining:?} remaining"
+                )
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DeferredTransition {
+    pub from: SystemState,
+    pub to: SystemState,
+    pub reason: DeferralReason,
+}
+
+/// Remembers when the plant last switched so transitions that would short cycle
+/// the equipment can be held back.
+#[derive(Default)]
+pub struct ShortCycleGuard {
+    state_entered_at: Option<std::time::Instant>,
+    last_active: Option<(SystemState, std::time::Instant)>,
+}
+
+fn remaining(
+    minimum: std::time::Duration,
+    since: std::time::Instant,
+    now: std::time::Instant,
+) -> Option<std::time::Duration> {
+    let elapsed = now.saturating_duration_since(since);
+    if elapsed < minimum {
+        Some(minimum - elapsed)
+    } else {
+        None
+    }
+}
+
+impl ShortCycleGuard {
+    pub fn check(
+        &self,
+        from: SystemState,
+        to: SystemState,
+        config: &Config,
+        now: std::time::Instant,
+    ) -> Result<(), DeferralReason> {
+        if from == to {
+            return Ok(());
+        }
+
+        if from != SystemState::Idle {
+            if let Some(entered_at) = self.state_entered_at {
+                if let Some(remaining) = remaining(config.min_run_time, entered_at, now) {
+                    return Err(DeferralReason::MinimumRunTime { remaining });
+                }
+            }
+        }
+
+        if to != SystemState::Idle {
+            let (last_state, stopped_at) = match (from, self.last_active) {
+                (SystemState::Idle, Some(last_active)) => last_active,
+                (SystemState::Idle, None) => return Ok(()),
+                // Switching straight over means the current run stops now,
+                // so the controller has to go idle and wait from there.
+                (active, _) => (active, now),
+            };
+            if last_state != to {
+                if let Some(remaining) = remaining(config.min_changeover_delay, stopped_at, now) {
+                    return Err(DeferralReason::ChangeoverDelay { remaining });
+                }
+            }
+            if let Some(remaining) = remaining(config.min_off_time, stopped_at, now) {
+                return Err(DeferralReason::MinimumOffTime { remaining });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn record_transition(
+        &mut self,
+        from: SystemState,
+        to: SystemState,
+        now: std::time::Instant,
+    ) {
+        if from == to {
+            return;
+        }
+        if from != SystemState::Idle {
+            self.last_active = Some((from, now));
+        }
+        self.state_entered_at = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            min_run_time: std::time::Duration::from_secs(300),
+            min_off_time: std::time::Duration::from_secs(180),
+            min_changeover_delay: std::time::Duration::from_secs(600),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn first_start_is_allowed() {
+        let guard = ShortCycleGuard::default();
+        let now = std::time::Instant::now();
+
+        let result = guard.check(SystemState::Idle, SystemState::Heating, &config(), now);
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn stopping_before_minimum_run_time_is_deferred() {
+        let mut guard = ShortCycleGuard::default();
+        let start = std::time::Instant::now();
+        guard.record_transition(SystemState::Idle, SystemState::Heating, start);
+
+        let result = guard.check(
+            SystemState::Heating,
+            SystemState::Idle,
+            &config(),
+            start + std::time::Duration::from_secs(100),
+        );
+
+        assert!(
+            result
+                == Err(DeferralReason::MinimumRunTime {
+                    remaining: std::time::Duration::from_secs(200)
+                })
+        );
+    }
+
+    #[test]
+    fn restarting_before_minimum_off_time_is_deferred() {
+        let mut guard = ShortCycleGuard::default();
+        let start = std::time::Instant::now();
+        let stop = start + std::time::Duration::from_secs(400);
+        guard.record_transition(SystemState::Idle, SystemState::Heating, start);
+        guard.record_transition(SystemState::Heating, SystemState::Idle, stop);
+
+        let deferred = guard.check(
+            SystemState::Idle,
+            SystemState::Heating,
+            &config(),
+            stop + std::time::Duration::from_secs(60),
+        );
+        let allowed = guard.check(
+            SystemState::Idle,
+            SystemState::Heating,
+            &config(),
+            stop + std::time::Duration::from_secs(180),
+        );
+
+        assert!(
+            deferred
+                == Err(DeferralReason::MinimumOffTime {
+                    remaining: std::time::Duration::from_secs(120)
+                })
+        );
+        assert!(allowed.is_ok());
+    }
+
+    #[test]
+    fn direct_changeover_is_deferred_from_now() {
+        let mut guard = ShortCycleGuard::default();
+        let start = std::time::Instant::now();
+        let now = start + std::time::Duration::from_secs(400);
+        guard.record_transition(SystemState::Idle, SystemState::Heating, start);
+
+        let result = guard.check(SystemState::Heating, SystemState::Cooling, &config(), now);
+
+        assert!(
+            result
+                == Err(DeferralReason::ChangeoverDelay {
+                    remaining: std::time::Duration::from_secs(600)
+                })
+        );
+    }
+
+    #[test]
+    fn changeover_waits_for_delay() {
+        let mut guard = ShortCycleGuard::default();
+        let start = std::time::Instant::now();
+        let stop = start + std::time::Duration::from_secs(400);
+        guard.record_transition(SystemState::Idle, SystemState::Heating, start);
+        guard.record_transition(SystemState::Heating, SystemState::Idle, stop);
+
+        let result = guard.check(
+            SystemState::Idle,
+            SystemState::Cooling,
+            &config(),
+            stop + std::time::Duration::from_secs(300),
+        );
+
+        assert!(
+            result
+                == Err(DeferralReason::ChangeoverDelay {
+                    remaining: std::time::Duration::from_secs(300)
+                })
+        );
+    }
+}
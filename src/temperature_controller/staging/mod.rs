@@ -0,0 +1,282 @@
+use crate::temperature_controller::SystemState;
+use crate::temperature_modifier::{
+    handle::{ActuatorHandle, OperationStatus},
+    ActuatorError, ModifyTemperature,
+};
+
+/// Equipment stages in the order they are brought in. `First` is the
+/// controller's own actuator; later stages are added on top of it.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Stage {
+    First,
+    Second,
+    Auxiliary,
+}
+
+/// When a stage is brought in and let go. The error is how far the
+/// temperature is from the setpoint in the direction being corrected.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct StageSettings {
+    pub stage: Stage,
+    /// The stage is brought in once the error reaches this value...
+    pub engage_error: f32,
+    /// ...and the stage below it has been running for this long.
+    pub engage_delay: std::time::Duration,
+    /// The stage is let go once the error falls to this value...
+    pub release_error: f32,
+    /// ...and the stage has been running for at least this long.
+    pub min_run_time: std::time::Duration,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StageChange {
+    Escalate,
+    DeEscalate,
+}
+
+struct ExtraStage {
+    settings: StageSettings,
+    modifier: Box<dyn ModifyTemperature>,
+    handle: Option<ActuatorHandle>,
+    engaged_at: Option<std::time::Instant>,
+}
+
+/// The stages above `First` for one direction, escalated and de-escalated one
+/// at a time.
+pub struct StagedEquipment {
+    direction: SystemState,
+    stages: Vec<ExtraStage>,
+    engaged: usize,
+    stage_since: Option<std::time::Instant>,
+}
+
+impl StagedEquipment {
+    /// `direction` is the state the stages help with, `Heating` or `Cooling`.
+    pub fn new(direction: SystemState) -> Self {
+        StagedEquipment {
+            direction,
+            stages: Vec::new(),
+            engaged: 0,
+            stage_since: None,
+        }
+    }
+
+    pub fn add_stage(&mut self, settings: StageSettings, modifier: Box<dyn ModifyTemperature>) {
+        self.stages.push(ExtraStage {
+            settings,
+            modifier,
+            handle: None,
+            engaged_at: None,
+        });
+    }
+
+    pub fn current_stage(&self) -> Stage {
+        match self.engaged {
+            0 => Stage::First,
+            engaged => self.stages[engaged - 1].settings.stage,
+        }
+    }
+
+    /// Decides whether a stage should be brought in or let go, starting the
+    /// time-in-stage count on the first call of a run.
+    pub fn evaluate(&mut self, error: f32, now: std::time::Instant) -> Option<StageChange> {
+        let stage_since = *self.stage_since.get_or_insert(now);
+        if let Some(next) = self.stages.get(self.engaged) {
+            if error >= next.settings.engage_error
+                && now.duration_since(stage_since) >= next.settings.engage_delay
+            {
+                return Some(StageChange::Escalate);
+            }
+        }
+        let top = self.stages[..self.engaged].last()?;
+        let run_time = top
+            .engaged_at
+            .map_or(std::time::Duration::ZERO, |engaged_at| {
+                now.duration_since(engaged_at)
+            });
+        if error <= top.settings.release_error && run_time >= top.settings.min_run_time {
+            return Some(StageChange::DeEscalate);
+        }
+        None
+    }
+
+    /// Starts the next stage driving towards `target_temperature`.
+    pub fn escalate(
+        &mut self,
+        target_temperature: f32,
+        now: std::time::Instant,
+    ) -> Result<Stage, ActuatorError> {
+        let stage = &mut self.stages[self.engaged];
+        let handle = match self.direction {
+            SystemState::Cooling => stage.modifier.lower_temperature(target_temperature)?,
+            _ => stage.modifier.raise_temperature(target_temperature)?,
+        };
+        stage.handle = Some(handle);
+        stage.engaged_at = Some(now);
+        self.engaged += 1;
+        self.stage_since = Some(now);
+        Ok(self.current_stage())
+    }
+
+    /// Stops the highest running stage.
+    pub fn de_escalate(&mut self, now: std::time::Instant) -> Stage {
+        if self.engaged > 0 {
+            self.engaged -= 1;
+            Self::release(&mut self.stages[self.engaged]);
+            self.stage_since = Some(now);
+        }
+        self.current_stage()
+    }
+
+    /// Returns how the runs of stages that failed since the last call ended.
+    /// Each failure is only returned once.
+    pub fn poll_failures(&mut self) -> Vec<(Stage, ActuatorError)> {
+        let mut failures = Vec::new();
+        for stage in self.stages[..self.engaged].iter_mut() {
+            let status = stage.handle.as_ref().map(ActuatorHandle::poll);
+            if let Some(OperationStatus::Failed(err)) = status {
+                stage.handle = None;
+                failures.push((stage.settings.stage, err));
+            }
+        }
+        failures
+    }
+
+    /// Stops the running stages that have run for their minimum run time,
+    /// from the top down, e.g. when the run ends. A stage that has not keeps
+    /// itself and the stages below it running until a later call.
+    pub fn release_due(&mut self, now: std::time::Instant) {
+        while let Some(top) = self.stages[..self.engaged].last() {
+            let run_time = top
+                .engaged_at
+                .map_or(std::time::Duration::ZERO, |engaged_at| {
+                    now.duration_since(engaged_at)
+                });
+            if run_time < top.settings.min_run_time {
+                return;
+            }
+            self.engaged -= 1;
+            Self::release(&mut self.stages[self.engaged]);
+        }
+        self.stage_since = None;
+    }
+
+    /// Stops every extra stage straight away, e.g. on a changeover or when
+    /// shutting down.
+    pub fn release_all(&mut self) {
+        for stage in self.stages[..self.engaged].iter_mut() {
+            Self::release(stage);
+        }
+        self.engaged = 0;
+        self.stage_since = None;
+    }
+
+    fn release(stage: &mut ExtraStage) {
+        if let Some(handle) = stage.handle.take() {
+            handle.cancel();
+        }
+        stage.engaged_at = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temperature_modifier::MockModifyTemperature;
+
+    const SECOND_STAGE: StageSettings = StageSettings {
+        stage: Stage::Second,
+        engage_error: 3.0,
+        engage_delay: std::time::Duration::from_secs(600),
+        release_error: 1.0,
+        min_run_time: std::time::Duration::from_secs(300),
+    };
+
+    fn heating_equipment() -> StagedEquipment {
+        let mut modifier = Box::new(MockModifyTemperature::new());
+        modifier
+            .expect_raise_temperature()
+            .returning(|_| Ok(ActuatorHandle::completed()));
+        let mut equipment = StagedEquipment::new(SystemState::Heating);
+        equipment.add_stage(SECOND_STAGE, modifier);
+        equipment
+    }
+
+    #[test]
+    fn escalates_after_time_in_stage() {
+        let start = std::time::Instant::now();
+        let mut equipment = heating_equipment();
+
+        assert!(equipment.evaluate(5.0, start).is_none());
+        let change = equipment.evaluate(5.0, start + std::time::Duration::from_secs(600));
+        assert!(change == Some(StageChange::Escalate));
+
+        let stage = equipment.escalate(20.0, start + std::time::Duration::from_secs(600));
+        assert!(stage == Ok(Stage::Second));
+        assert!(equipment.current_stage() == Stage::Second);
+    }
+
+    #[test]
+    fn small_error_does_not_escalate() {
+        let start = std::time::Instant::now();
+        let mut equipment = heating_equipment();
+
+        assert!(equipment.evaluate(2.0, start).is_none());
+        let change = equipment.evaluate(2.0, start + std::time::Duration::from_secs(3600));
+        assert!(change.is_none());
+    }
+
+    #[test]
+    fn run_end_keeps_stage_for_minimum_run_time() {
+        let start = std::time::Instant::now();
+        let mut equipment = heating_equipment();
+        equipment.evaluate(5.0, start);
+        assert!(equipment.escalate(20.0, start).is_ok());
+
+        equipment.release_due(start + std::time::Duration::from_secs(100));
+        assert!(equipment.current_stage() == Stage::Second);
+        equipment.release_due(start + std::time::Duration::from_secs(300));
+        assert!(equipment.current_stage() == Stage::First);
+    }
+
+    #[test]
+    fn stage_failure_is_reported_once() {
+        let start = std::time::Instant::now();
+        let (handle, progress) = ActuatorHandle::pending();
+        let mut modifier = Box::new(MockModifyTemperature::new());
+        modifier
+            .expect_raise_temperature()
+            .return_once(move |_| Ok(handle));
+        let mut equipment = StagedEquipment::new(SystemState::Heating);
+        equipment.add_stage(SECOND_STAGE, modifier);
+        equipment.evaluate(5.0, start);
+        assert!(equipment.escalate(20.0, start).is_ok());
+        assert!(equipment.poll_failures().is_empty());
+
+        progress.finish(OperationStatus::Failed(ActuatorError::Stalled {
+            rate: 0.0,
+        }));
+
+        assert!(
+            equipment.poll_failures()
+                == vec![(Stage::Second, ActuatorError::Stalled { rate: 0.0 })]
+        );
+        assert!(equipment.poll_failures().is_empty());
+    }
+
+    #[test]
+    fn de_escalates_after_minimum_run_time() {
+        let start = std::time::Instant::now();
+        let mut equipment = heating_equipment();
+        equipment.evaluate(5.0, start);
+        assert!(equipment.escalate(20.0, start).is_ok());
+
+        let change = equipment.evaluate(0.5, start + std::time::Duration::from_secs(100));
+        assert!(change.is_none());
+        let change = equipment.evaluate(0.5, start + std::time::Duration::from_secs(300));
+        assert!(change == Some(StageChange::DeEscalate));
+
+        let stage = equipment.de_escalate(start + std::time::Duration::from_secs(300));
+        assert!(stage == Stage::First);
+    }
+}
@@ -0,0 +1,139 @@
+use crate::temperature_modifier::ActuatorError;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum OperationStatus {
+    Running,
+    Completed,
+    Cancelled,
+    Failed(ActuatorError),
+}
+
+struct Operation {
+    status: std::sync::Mutex<OperationStatus>,
+    finished: std::sync::Condvar,
+    cancel_requested: std::sync::atomic::AtomicBool,
+}
+
+impl Operation {
+    fn with_status(status: OperationStatus) -> std::sync::Arc<Self> {
+        std::sync::Arc::new(Operation {
+            status: std::sync::Mutex::new(status),
+            finished: std::sync::Condvar::new(),
+            cancel_requested: std::sync::atomic::AtomicBool::new(false),
+        })
+    }
+}
+
+/// Handle to a heating or cooling run started by `ModifyTemperature`. The run
+/// carries on in the background until it finishes or is cancelled.
+#[derive(Clone)]
+pub struct ActuatorHandle {
+    operation: std::sync::Arc<Operation>,
+}
+
+/// The actuator's side of an `ActuatorHandle`, used to watch for cancellation
+/// and report how the run ended.
+pub struct OperationProgress {
+    operation: std::sync::Arc<Operation>,
+}
+
+impl ActuatorHandle {
+    pub fn pending() -> (ActuatorHandle, OperationProgress) {
+        let operation = Operation::with_status(OperationStatus::Running);
+        (
+            ActuatorHandle {
+                operation: operation.clone(),
+            },
+            OperationProgress { operation },
+        )
+    }
+
+    /// A handle for a run that finished before it was handed out.
+    pub fn completed() -> Self {
+        ActuatorHandle {
+            operation: Operation::with_status(OperationStatus::Completed),
+        }
+    }
+
+    pub fn poll(&self) -> OperationStatus {
+        self.operation.status.lock().unwrap().clone()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.poll() != OperationStatus::Running
+    }
+
+    /// Asks the actuator to stop; `poll` reports `Cancelled` once it has.
+    pub fn cancel(&self) {
+        self.operation
+            .cancel_requested
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    pub fn wait(&self) -> OperationStatus {
+        let mut status = self.operation.status.lock().unwrap();
+        while *status == OperationStatus::Running {
+            status = self.operation.finished.wait(status).unwrap();
+        }
+        status.clone()
+    }
+}
+
+impl OperationProgress {
+    pub fn is_cancelled(&self) -> bool {
+        self.operation
+            .cancel_requested
+            .load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    pub fn finish(self, status: OperationStatus) {
+        *self.operation.status.lock().unwrap() = status;
+        self.operation.finished.notify_all();
+    }
+}
+
+impl Drop for OperationProgress {
+    fn drop(&mut self) {
+        let mut status = self.operation.status.lock().unwrap();
+        if *status == OperationStatus::Running {
+            *status = OperationStatus::Failed(ActuatorError::failed(
+                "Actuator stopped without reporting",
+            ));
+            self.operation.finished.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pending_until_finished() {
+        let (handle, progress) = ActuatorHandle::pending();
+        assert!(handle.poll() == OperationStatus::Running);
+
+        progress.finish(OperationStatus::Completed);
+
+        assert!(handle.poll() == OperationStatus::Completed);
+    }
+
+    #[test]
+    fn cancel_is_seen_by_actuator() {
+        let (handle, progress) = ActuatorHandle::pending();
+
+        handle.cancel();
+
+        assert!(progress.is_cancelled());
+        assert!(!handle.is_finished());
+    }
+
+    #[test]
+    fn dropped_progress_fails_operation() {
+        let (handle, progress) = ActuatorHandle::pending();
+
+        drop(progress);
+
+        assert!(matches!(handle.wait(), OperationStatus::Failed(_)));
+    }
+}
@@ -1,48 +1,200 @@
+use crate::clock::{Clock, SystemClock};
 use crate::temperature_value_provider::TemperatureValueProvider;
 
-#[mockall::automock]
-pub trait ModifyTemperature {
-    fn lower_temperature(&self, target_temperature: f32) -> Result<(), &'static str>;
-    fn raise_temperature(&self, target_temperature: f32) -> Result<(), &'static str>;
+pub mod handle;
+pub mod run_limits;
+
+use handle::{ActuatorHandle, OperationProgress, OperationStatus};
+use run_limits::{RunLimits, RunMonitor};
+
+#[derive(Clone, Debug)]
+pub enum ActuatorError {
+    /// The actuator could not carry out the command. The cause is shared so
+    /// that every handle polling the run sees it.
+    Failed(std::sync::Arc<dyn std::error::Error + Send + Sync>),
+    /// The temperature moved slower than `rate` degrees per second allows.
+    Stalled { rate: f32 },
+    /// The run went on for longer than allowed.
+    TimedOut { run_time: std::time::Duration },
 }
 
-pub struct TemperatureModifier {}
+impl ActuatorError {
+    pub fn failed(cause: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        ActuatorError::Failed(cause.into().into())
+    }
+
+    /// Whether the actuator was switched off because the plant is not
+    /// responding, as opposed to the actuator refusing the command.
+    pub fn is_fault(&self) -> bool {
+        matches!(
+            self,
+            ActuatorError::Stalled { .. } | ActuatorError::TimedOut { .. }
+        )
+    }
+}
 
-impl ModifyTemperature for TemperatureModifier {
-    fn lower_temperature(&self, target_temperature: f32) -> Result<(), &'static str> {
-        let mut current_temperature: f32;
-        loop {
-            current_temperature = TemperatureValueProvider::get_current_temperature();
-            if current_temperature > target_temperature {
-                TemperatureValueProvider::set_current_temperature(current_temperature - 1.0);
-                std::thread::sleep(std::time::Duration::from_millis(100));
-            } else {
-                return Ok(());
+impl std::fmt::Display for ActuatorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ActuatorError::Failed(_) => write!(f, "Actuator failed"),
+            ActuatorError::Stalled { rate } => write!(
+                f,
+                "Actuator stalled: temperature moving at {rate} degrees per second"
+            ),
+            ActuatorError::TimedOut { run_time } => {
+                write!(f, "Actuator timed out after {}s", run_time.as_secs_f32())
             }
         }
     }
+}
+
+impl std::error::Error for ActuatorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ActuatorError::Failed(cause) => Some(cause.as_ref()),
+            _ => None,
+        }
+    }
+}
 
-    fn raise_temperature(&self, target_temperature: f32) -> Result<(), &'static str> {
-        let mut current_temperature: f32;
-        loop {
-            current_temperature = TemperatureValueProvider::get_current_temperature();
-            if current_temperature < target_temperature {
-                TemperatureValueProvider::set_current_temperature(current_temperature + 1.0);
-                std::thread::sleep(std::time::Duration::from_millis(100));
-            } else {
-                return Ok(());
+/// A failure only equals itself, as causes cannot be compared; faults compare
+/// by value.
+impl PartialEq for ActuatorError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ActuatorError::Failed(cause), ActuatorError::Failed(other_cause)) => {
+                std::sync::Arc::ptr_eq(cause, other_cause)
+            }
+            (ActuatorError::Stalled { rate }, ActuatorError::Stalled { rate: other_rate }) => {
+                rate == other_rate
             }
+            (
+                ActuatorError::TimedOut { run_time },
+                ActuatorError::TimedOut {
+                    run_time: other_run_time,
+                },
+            ) => run_time == other_run_time,
+            _ => false,
+        }
+    }
+}
+
+/// Starts driving the temperature towards a target and returns straight away;
+/// the returned handle tracks the run.
+#[mockall::automock]
+pub trait ModifyTemperature {
+    fn lower_temperature(&self, target_temperature: f32) -> Result<ActuatorHandle, ActuatorError>;
+    fn raise_temperature(&self, target_temperature: f32) -> Result<ActuatorHandle, ActuatorError>;
+}
+
+const STEP_DURATION: std::time::Duration = std::time::Duration::from_millis(100);
+
+pub struct TemperatureModifier {
+    clock: std::sync::Arc<dyn Clock>,
+    run_limits: RunLimits,
+}
+
+impl TemperatureModifier {
+    pub fn new(clock: std::sync::Arc<dyn Clock>) -> Self {
+        TemperatureModifier {
+            clock,
+            run_limits: RunLimits::default(),
+        }
+    }
+
+    pub fn with_run_limits(mut self, run_limits: RunLimits) -> Self {
+        self.run_limits = run_limits;
+        self
+    }
+}
+
+impl Default for TemperatureModifier {
+    fn default() -> Self {
+        TemperatureModifier::new(std::sync::Arc::new(SystemClock::default()))
+    }
+}
+
+impl TemperatureModifier {
+    fn start(&self, target_temperature: f32, step: f32) -> Result<ActuatorHandle, ActuatorError> {
+        let (handle, progress) = ActuatorHandle::pending();
+        let clock = self.clock.clone();
+        let monitor = RunMonitor::new(
+            self.run_limits,
+            step,
+            clock.now(),
+            TemperatureValueProvider::get_current_temperature(),
+        );
+        std::thread::Builder::new()
+            .name("temperature-modifier".to_string())
+            .spawn(move || {
+                drive_temperature(clock.as_ref(), progress, monitor, target_temperature, step)
+            })
+            .map_err(ActuatorError::failed)?;
+        Ok(handle)
+    }
+}
+
+fn drive_temperature(
+    clock: &dyn Clock,
+    progress: OperationProgress,
+    mut monitor: RunMonitor,
+    target_temperature: f32,
+    step: f32,
+) {
+    loop {
+        if progress.is_cancelled() {
+            progress.finish(OperationStatus::Cancelled);
+            return;
+        }
+        let current_temperature = TemperatureValueProvider::get_current_temperature();
+        let remaining = (target_temperature - current_temperature) * step.signum();
+        if remaining <= 0.0 {
+            progress.finish(OperationStatus::Completed);
+            return;
         }
+        // Returning without another step de-energises the actuator.
+        if let Err(err) = monitor.check(clock.now(), current_temperature) {
+            progress.finish(OperationStatus::Failed(err));
+            return;
+        }
+        TemperatureValueProvider::set_current_temperature(current_temperature + step);
+        clock.sleep(STEP_DURATION);
+    }
+}
+
+impl ModifyTemperature for TemperatureModifier {
+    fn lower_temperature(&self, target_temperature: f32) -> Result<ActuatorHandle, ActuatorError> {
+        self.start(target_temperature, -1.0)
+    }
+
+    fn raise_temperature(&self, target_temperature: f32) -> Result<ActuatorHandle, ActuatorError> {
+        self.start(target_temperature, 1.0)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn failure_keeps_its_cause() {
+        let err = ActuatorError::failed(std::io::Error::new(
+            std::io::ErrorKind::BrokenPipe,
+            "relay bus down",
+        ));
+
+        let source = std::error::Error::source(&err);
+        assert!(source.map(|source| source.to_string()) == Some("relay bus down".to_string()));
+        assert!(err == err.clone());
+        assert!(err != ActuatorError::failed("relay bus down"));
+    }
 
     #[test]
     fn raise_above_current() {
-        let temperature_modifier = TemperatureModifier {};
+        let _simulation = TemperatureValueProvider::lock_for_test();
+        let clock = std::sync::Arc::new(ManualClock::default());
+        let temperature_modifier = TemperatureModifier::new(clock.clone());
         let initial_temperature = 5f32;
         TemperatureValueProvider::set_current_temperature(initial_temperature);
         let expected_temperature = 10f32;
@@ -51,6 +203,7 @@ mod tests {
         let temperature_was_modified = temperature_modifier.raise_temperature(target_temperature);
 
         assert!(temperature_was_modified.is_ok());
+        assert!(temperature_was_modified.unwrap().wait() == OperationStatus::Completed);
         let current_temperature = TemperatureValueProvider::get_current_temperature();
         assert!(float_cmp::approx_eq!(
             f32,
@@ -58,11 +211,14 @@ mod tests {
             expected_temperature,
             epsilon = 0.000001
         ));
+        assert!(clock.elapsed() == STEP_DURATION * 5);
     }
 
     #[test]
     fn raise_below_current() {
-        let temperature_modifier = TemperatureModifier {};
+        let _simulation = TemperatureValueProvider::lock_for_test();
+        let temperature_modifier =
+            TemperatureModifier::new(std::sync::Arc::new(ManualClock::default()));
         let initial_temperature = 5f32;
         TemperatureValueProvider::set_current_temperature(initial_temperature);
         let expected_temperature = 5f32;
@@ -71,6 +227,7 @@ mod tests {
         let temperature_was_modified = temperature_modifier.raise_temperature(target_temperature);
 
         assert!(temperature_was_modified.is_ok());
+        assert!(temperature_was_modified.unwrap().wait() == OperationStatus::Completed);
         let current_temperature = TemperatureValueProvider::get_current_temperature();
         assert!(float_cmp::approx_eq!(
             f32,
@@ -82,7 +239,9 @@ mod tests {
 
     #[test]
     fn lower_below_current() {
-        let temperature_modifier = TemperatureModifier {};
+        let _simulation = TemperatureValueProvider::lock_for_test();
+        let temperature_modifier =
+            TemperatureModifier::new(std::sync::Arc::new(ManualClock::default()));
         let initial_temperature = 5f32;
         TemperatureValueProvider::set_current_temperature(initial_temperature);
         let expected_temperature = 3f32;
@@ -91,6 +250,7 @@ mod tests {
         let temperature_was_modified = temperature_modifier.lower_temperature(target_temperature);
 
         assert!(temperature_was_modified.is_ok());
+        assert!(temperature_was_modified.unwrap().wait() == OperationStatus::Completed);
         let current_temperature = TemperatureValueProvider::get_current_temperature();
         assert!(float_cmp::approx_eq!(
             f32,
@@ -102,7 +262,9 @@ mod tests {
 
     #[test]
     fn lower_above_current() {
-        let temperature_modifier = TemperatureModifier {};
+        let _simulation = TemperatureValueProvider::lock_for_test();
+        let temperature_modifier =
+            TemperatureModifier::new(std::sync::Arc::new(ManualClock::default()));
         let initial_temperature = 5f32;
         TemperatureValueProvider::set_current_temperature(initial_temperature);
         let expected_temperature = 5f32;
@@ -111,6 +273,7 @@ mod tests {
         let temperature_was_modified = temperature_modifier.lower_temperature(target_temperature);
 
         assert!(temperature_was_modified.is_ok());
+        assert!(temperature_was_modified.unwrap().wait() == OperationStatus::Completed);
         let current_temperature = TemperatureValueProvider::get_current_temperature();
         assert!(float_cmp::approx_eq!(
             f32,
@@ -119,4 +282,74 @@ mod tests {
             epsilon = 0.000001
         ));
     }
+
+    #[test]
+    fn cancel_stops_raising() {
+        let _simulation = TemperatureValueProvider::lock_for_test();
+        let temperature_modifier = TemperatureModifier::default();
+        let initial_temperature = 5f32;
+        TemperatureValueProvider::set_current_temperature(initial_temperature);
+        let target_temperature = 1000f32;
+
+        let handle = temperature_modifier.raise_temperature(target_temperature);
+        assert!(handle.is_ok());
+        let handle = handle.unwrap();
+        handle.cancel();
+
+        assert!(handle.wait() == OperationStatus::Cancelled);
+        let current_temperature = TemperatureValueProvider::get_current_temperature();
+        assert!(current_temperature < initial_temperature + 5.0);
+    }
+
+    #[test]
+    fn slow_run_stalls() {
+        let _simulation = TemperatureValueProvider::lock_for_test();
+        let temperature_modifier = TemperatureModifier::new(std::sync::Arc::new(
+            ManualClock::default(),
+        ))
+        .with_run_limits(RunLimits {
+            stall_window: STEP_DURATION * 2,
+            min_rate: 100f32,
+            ..RunLimits::default()
+        });
+        TemperatureValueProvider::set_current_temperature(5f32);
+
+        let temperature_was_modified = temperature_modifier.raise_temperature(10f32);
+
+        assert!(temperature_was_modified.is_ok());
+        let status = temperature_was_modified.unwrap().wait();
+        assert!(matches!(
+            status,
+            OperationStatus::Failed(ActuatorError::Stalled { .. })
+        ));
+        let current_temperature = TemperatureValueProvider::get_current_temperature();
+        assert!(float_cmp::approx_eq!(
+            f32,
+            current_temperature,
+            7f32,
+            epsilon = 0.000001
+        ));
+    }
+
+    #[test]
+    fn long_run_times_out() {
+        let _simulation = TemperatureValueProvider::lock_for_test();
+        let clock = std::sync::Arc::new(ManualClock::default());
+        let temperature_modifier =
+            TemperatureModifier::new(clock.clone()).with_run_limits(RunLimits {
+                max_run_time: STEP_DURATION * 3,
+                ..RunLimits::default()
+            });
+        TemperatureValueProvider::set_current_temperature(5f32);
+
+        let temperature_was_modified = temperature_modifier.lower_temperature(-10f32);
+
+        assert!(temperature_was_modified.is_ok());
+        let status = temperature_was_modified.unwrap().wait();
+        assert!(matches!(
+            status,
+            OperationStatus::Failed(ActuatorError::TimedOut { .. })
+        ));
+        assert!(clock.elapsed() == STEP_DURATION * 3);
+    }
 }
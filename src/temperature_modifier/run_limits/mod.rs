@@ -0,0 +1,138 @@
+use crate::temperature_modifier::ActuatorError;
+
+pub const DEFAULT_MAX_RUN_TIME: std::time::Duration = std::time::Duration::from_secs(30 * 60);
+pub const DEFAULT_STALL_WINDOW: std::time::Duration = std::time::Duration::from_secs(60);
+pub const DEFAULT_MIN_RATE: f32 = 0.01;
+
+/// Bounds on a single heating or cooling run. `min_rate` is in degrees per
+/// second and is measured over each `stall_window`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RunLimits {
+    pub max_run_time: std::time::Duration,
+    pub stall_window: std::time::Duration,
+    pub min_rate: f32,
+}
+
+impl Default for RunLimits {
+    fn default() -> Self {
+        RunLimits {
+            max_run_time: DEFAULT_MAX_RUN_TIME,
+            stall_window: DEFAULT_STALL_WINDOW,
+            min_rate: DEFAULT_MIN_RATE,
+        }
+    }
+}
+
+/// Watches one run and reports when it has gone on too long or the
+/// temperature stopped moving in the commanded direction.
+pub struct RunMonitor {
+    limits: RunLimits,
+    direction: f32,
+    started: std::time::Instant,
+    window_start: std::time::Instant,
+    window_temperature: f32,
+}
+
+impl RunMonitor {
+    /// `direction` is positive when heating and negative when cooling.
+    pub fn new(
+        limits: RunLimits,
+        direction: f32,
+        now: std::time::Instant,
+        temperature: f32,
+    ) -> Self {
+        RunMonitor {
+            limits,
+            direction: direction.signum(),
+            started: now,
+            window_start: now,
+            window_temperature: temperature,
+        }
+    }
+
+    pub fn check(
+        &mut self,
+        now: std::time::Instant,
+        temperature: f32,
+    ) -> Result<(), ActuatorError> {
+        let run_time = now.duration_since(self.started);
+        if run_time >= self.limits.max_run_time {
+            return Err(ActuatorError::TimedOut { run_time });
+        }
+        let window = now.duration_since(self.window_start);
+        if window >= self.limits.stall_window && !window.is_zero() {
+            let rate =
+                (temperature - self.window_temperature) * self.direction / window.as_secs_f32();
+            if rate < self.limits.min_rate {
+                return Err(ActuatorError::Stalled { rate });
+            }
+            self.window_start = now;
+            self.window_temperature = temperature;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: RunLimits = RunLimits {
+        max_run_time: std::time::Duration::from_secs(600),
+        stall_window: std::time::Duration::from_secs(60),
+        min_rate: 0.01,
+    };
+
+    #[test]
+    fn moving_temperature_passes() {
+        let start = std::time::Instant::now();
+        let mut monitor = RunMonitor::new(LIMITS, 1.0, start, 5f32);
+
+        let checked = monitor.check(start + std::time::Duration::from_secs(60), 6f32);
+        assert!(checked.is_ok());
+        let checked = monitor.check(start + std::time::Duration::from_secs(120), 7f32);
+        assert!(checked.is_ok());
+    }
+
+    #[test]
+    fn flat_temperature_stalls() {
+        let start = std::time::Instant::now();
+        let mut monitor = RunMonitor::new(LIMITS, 1.0, start, 5f32);
+
+        let checked = monitor.check(start + std::time::Duration::from_secs(30), 5f32);
+        assert!(checked.is_ok());
+        let checked = monitor.check(start + std::time::Duration::from_secs(60), 5.1f32);
+        assert!(matches!(checked, Err(ActuatorError::Stalled { .. })));
+    }
+
+    #[test]
+    fn wrong_direction_stalls() {
+        let start = std::time::Instant::now();
+        let mut monitor = RunMonitor::new(LIMITS, -1.0, start, 5f32);
+
+        let checked = monitor.check(start + std::time::Duration::from_secs(60), 6f32);
+        assert!(matches!(checked, Err(ActuatorError::Stalled { .. })));
+    }
+
+    #[test]
+    fn long_run_times_out() {
+        let start = std::time::Instant::now();
+        let mut monitor = RunMonitor::new(
+            RunLimits {
+                stall_window: std::time::Duration::from_secs(3600),
+                ..LIMITS
+            },
+            1.0,
+            start,
+            5f32,
+        );
+
+        let checked = monitor.check(start + std::time::Duration::from_secs(600), 50f32);
+        assert!(
+            checked
+                == Err(ActuatorError::TimedOut {
+                    run_time: std::time::Duration::from_secs(600)
+                })
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::temperature_sensor::FetchTemperature;
+use crate::temperature_sensor::{FetchTemperature, SensorError};
 
 pub struct TemperatureSensorHttp {
     resource_path: &'static str,
@@ -13,49 +13,70 @@ impl Default for TemperatureSensorHttp {
 }
 
 impl FetchTemperature for TemperatureSensorHttp {
-    fn get_current_temperature(&self) -> Option<f32> {
-        let temperature = match reqwest::blocking::get(self.resource_path) {
-            Ok(val) => val,
-            Err(val) => {
-                eprintln!(
-                    "Failed to get data from resource {} : {val}",
-                    self.resource_path
-                );
-                return None;
-            }
-        };
+    fn get_current_temperature(&self) -> Result<f32, SensorError> {
+        let temperature = reqwest::blocking::get(self.resource_path)
+            .map_err(|err| SensorError::Unreachable(Box::new(err)))?;
         if !temperature.status().is_success() {
-            eprintln!("Request failed with error {}", temperature.status());
-            return None;
+            return Err(SensorError::Unreachable(
+                format!(
+                    "Request to {} failed with error {}",
+                    self.resource_path,
+                    temperature.status()
+                )
+                .into(),
+            ));
         }
-        let temperature: serde_json::Value = match temperature.json() {
-            Ok(val) => val,
-            Err(err) => {
-                eprintln!("Failed to parse json: {err}");
-                return None;
-            }
-        };
+        let temperature: serde_json::Value = temperature
+            .json()
+            .map_err(|err| SensorError::Garbage(format!("Failed to parse json: {err}")))?;
         let temperature = match temperature.get("temperature") {
             Some(val) => match val.as_str() {
                 Some(temp) => temp,
                 None => {
-                    eprintln!("Failed to convert value to string");
-                    return None;
+                    return Err(SensorError::Garbage(
+                        "Failed to convert value to string".to_string(),
+                    ))
                 }
             },
             None => {
-                eprintln!("Value temperature does not exist");
-                return None;
+                return Err(SensorError::Garbage(
+                    "Value temperature does not exist".to_string(),
+                ))
             }
         };
-        match temperature.parse::<f32>() {
-            Ok(val) => {
-                return Some(val);
-            }
-            Err(err) => {
-                eprintln!("Failed to parse string to f32: {err}");
-                return None;
-            }
+        parse_temperature(temperature)
+    }
+}
+
+/// Rejects `NaN` and infinities too, which parse as `f32` but are no reading.
+fn parse_temperature(temperature: &str) -> Result<f32, SensorError> {
+    let temperature = temperature
+        .parse::<f32>()
+        .map_err(|err| SensorError::Garbage(format!("Failed to parse string to f32: {err}")))?;
+    if !temperature.is_finite() {
+        return Err(SensorError::Garbage(format!(
+            "{temperature} is not a temperature"
+        )));
+    }
+    Ok(temperature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_finite_readings_only() {
+        assert!(matches!(parse_temperature("21.5"), Ok(temperature) if temperature == 21.5));
+        assert!(matches!(
+            parse_temperature("warm"),
+            Err(SensorError::Garbage(_))
+        ));
+        for reading in ["NaN", "inf", "-inf"] {
+            assert!(matches!(
+                parse_temperature(reading),
+                Err(SensorError::Garbage(_))
+            ));
         }
     }
-}
\ No newline at end of file
+}
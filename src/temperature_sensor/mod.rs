@@ -1,6 +1,33 @@
 pub mod serial;
 pub mod http;
+
+#[derive(Debug)]
+pub enum SensorError {
+    /// The sensor could not be reached or refused to answer.
+    Unreachable(Box<dyn std::error::Error + Send + Sync>),
+    /// The sensor answered with something that is not a temperature.
+    Garbage(String),
+}
+
+impl std::fmt::Display for SensorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SensorError::Unreachable(_) => write!(f, "Sensor is unreachable"),
+            SensorError::Garbage(reason) => write!(f, "Sensor returned garbage: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for SensorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SensorError::Unreachable(source) => Some(source.as_ref()),
+            SensorError::Garbage(_) => None,
+        }
+    }
+}
+
 #[mockall::automock]
 pub trait FetchTemperature {
-    fn get_current_temperature(&self) -> Option<f32>;
-}
\ No newline at end of file
+    fn get_current_temperature(&self) -> Result<f32, SensorError>;
+}
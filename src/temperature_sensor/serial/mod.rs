@@ -1,18 +1,23 @@
-use crate::{temperature_sensor::FetchTemperature, temperature_value_provider::TemperatureValueProvider};
+use crate::{
+    temperature_sensor::{FetchTemperature, SensorError},
+    temperature_value_provider::TemperatureValueProvider,
+};
 
 pub struct TemperatureSensorSerial {}
 
 impl FetchTemperature for TemperatureSensorSerial {
-    fn get_current_temperature(&self) -> Option<f32> {
-        return Some(TemperatureValueProvider::get_current_temperature());
+    fn get_current_temperature(&self) -> Result<f32, SensorError> {
+        Ok(TemperatureValueProvider::get_current_temperature())
     }
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
     
     #[test]
     fn fetch_temperature() {
+        let _simulation = TemperatureValueProvider::lock_for_test();
         TemperatureValueProvider::set_current_temperature(5.0);
         let expected_temperature: f32 = 5f32;
         let sensor = TemperatureSensorSerial {};
@@ -3,6 +3,9 @@ lazy_static::lazy_static! {
 static ref TEMPERATURE: std::sync::Mutex<f32> = std::sync::Mutex::new(generate_random_temperature());
 }
 
+#[cfg(test)]
+static SIMULATION_TEST_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
 fn generate_random_temperature() -> f32 {
     rand::thread_rng().gen_range(-35..35) as f32
 }
@@ -11,10 +14,60 @@ pub struct TemperatureValueProvider {}
 
 impl TemperatureValueProvider {
     pub fn get_current_temperature() -> f32 {
-        return *TEMPERATURE.lock().unwrap();
+        *TEMPERATURE.lock().unwrap()
     }
 
     pub fn set_current_temperature(temp: f32) {
         *TEMPERATURE.lock().unwrap() = temp;
     }
+
+    /// Serialises tests that drive the shared simulated temperature.
+    #[cfg(test)]
+    pub fn lock_for_test() -> std::sync::MutexGuard<'static, ()> {
+        SIMULATION_TEST_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// First-order room model with dead time acting on the simulated temperature,
+/// for exercising control loops. `gain` is how far above `ambient` full
+/// heating would settle; `output` runs from -1 (full cooling) to 1.
+pub struct SimulatedPlant {
+    ambient: f32,
+    gain: f32,
+    time_constant: std::time::Duration,
+    step: std::time::Duration,
+    delayed_outputs: std::collections::VecDeque<f32>,
+}
+
+impl SimulatedPlant {
+    pub fn new(
+        ambient: f32,
+        gain: f32,
+        time_constant: std::time::Duration,
+        dead_time: std::time::Duration,
+        step: std::time::Duration,
+    ) -> Self {
+        let delay_steps = (dead_time.as_secs_f32() / step.as_secs_f32()).round() as usize;
+        SimulatedPlant {
+            ambient,
+            gain,
+            time_constant,
+            step,
+            delayed_outputs: std::iter::repeat_n(0.0, delay_steps).collect(),
+        }
+    }
+
+    /// Moves the simulated temperature on by one step.
+    pub fn advance(&mut self, output: f32) {
+        self.delayed_outputs.push_back(output.clamp(-1.0, 1.0));
+        let effective_output = self.delayed_outputs.pop_front().unwrap_or_default();
+        let current_temperature = TemperatureValueProvider::get_current_temperature();
+        let equilibrium = self.ambient + self.gain * effective_output;
+        let fraction = self.step.as_secs_f32() / self.time_constant.as_secs_f32();
+        TemperatureValueProvider::set_current_temperature(
+            current_temperature + (equilibrium - current_temperature) * fraction.min(1.0),
+        );
+    }
 }
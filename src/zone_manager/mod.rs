@@ -0,0 +1,315 @@
+use crate::config_reader::{Config, Mode};
+use crate::temperature_controller::{ControllerError, HandleTemperature, SystemState};
+
+/// Last known condition of one zone.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ZoneStatus {
+    pub name: String,
+    pub state: SystemState,
+    pub mode: Mode,
+    pub configured_setpoint: Option<f32>,
+    pub effective_setpoint: Option<f32>,
+    /// The error of the last failed cycle, cleared once a cycle succeeds.
+    pub last_error: Option<String>,
+    pub consecutive_failures: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct AggregateStatus {
+    pub zones: usize,
+    pub heating: usize,
+    pub cooling: usize,
+    pub failing: usize,
+}
+
+struct Zone {
+    name: String,
+    controller: Box<dyn HandleTemperature>,
+    last_error: Option<String>,
+    consecutive_failures: u32,
+}
+
+impl Zone {
+    fn record(&mut self, result: Result<(), ControllerError>) -> Result<(), ControllerError> {
+        match &result {
+            Ok(()) => {
+                self.last_error = None;
+                self.consecutive_failures = 0;
+            }
+            Err(err) => {
+                self.last_error = Some(err.to_string());
+                self.consecutive_failures += 1;
+            }
+        }
+        result
+    }
+}
+
+/// Runs several independent zones, each with its own controller, in one
+/// control loop. A zone that fails is reported but never stops the others
+/// from being updated.
+#[derive(Default)]
+pub struct ZoneManager {
+    zones: Vec<Zone>,
+}
+
+impl ZoneManager {
+    pub fn add_zone(&mut self, name: String, controller: Box<dyn HandleTemperature>) {
+        self.zones.push(Zone {
+            name,
+            controller,
+            last_error: None,
+            consecutive_failures: 0,
+        });
+    }
+
+    pub fn get_zone_status(&self, name: &str) -> Option<ZoneStatus> {
+        self.zones
+            .iter()
+            .find(|zone| zone.name == name)
+            .map(Self::status_of)
+    }
+
+    pub fn get_status(&self) -> Vec<ZoneStatus> {
+        self.zones.iter().map(Self::status_of).collect()
+    }
+
+    pub fn get_aggregate_status(&self) -> AggregateStatus {
+        let mut aggregate = AggregateStatus {
+            zones: self.zones.len(),
+            ..AggregateStatus::default()
+        };
+        for zone in self.zones.iter() {
+            match zone.controller.get_current_state() {
+                SystemState::Heating => aggregate.heating += 1,
+                SystemState::Cooling => aggregate.cooling += 1,
+                SystemState::Idle => {}
+            }
+            if zone.last_error.is_some() {
+                aggregate.failing += 1;
+            }
+        }
+        aggregate
+    }
+
+    fn status_of(zone: &Zone) -> ZoneStatus {
+        ZoneStatus {
+            name: zone.name.clone(),
+            state: zone.controller.get_current_state(),
+            mode: zone.controller.get_current_mode(),
+            configured_setpoint: zone.controller.get_config().map(|config| config.setpoint),
+            effective_setpoint: zone.controller.get_effective_setpoint(),
+            last_error: zone.last_error.clone(),
+            consecutive_failures: zone.consecutive_failures,
+        }
+    }
+
+    /// Runs `action` on every zone and reports every failure once all zones
+    /// had their turn.
+    fn for_each_zone(
+        &mut self,
+        action: impl Fn(&mut dyn HandleTemperature) -> Result<(), ControllerError>,
+    ) -> Result<(), ControllerError> {
+        let mut errors = Vec::new();
+        for zone in self.zones.iter_mut() {
+            let result = action(zone.controller.as_mut());
+            if let Err(err) = zone.record(result) {
+                errors.push(ControllerError::Zone {
+                    zone: zone.name.clone(),
+                    error: Box::new(err),
+                });
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(ControllerError::Zones(errors)),
+        }
+    }
+}
+
+/// Lets a `ControlLoopScheduler` drive all zones at once.
+impl HandleTemperature for ZoneManager {
+    fn update_temperature(&mut self) -> Result<(), ControllerError> {
+        self.for_each_zone(|controller| controller.update_temperature())
+    }
+
+    fn sample_temperature(&mut self) -> Result<(), ControllerError> {
+        self.for_each_zone(|controller| controller.sample_temperature())
+    }
+
+    /// Heating if any zone heats, otherwise cooling if any zone cools.
+    fn get_current_state(&self) -> SystemState {
+        let aggregate = self.get_aggregate_status();
+        if aggregate.heating > 0 {
+            SystemState::Heating
+        } else if aggregate.cooling > 0 {
+            SystemState::Cooling
+        } else {
+            SystemState::Idle
+        }
+    }
+
+    /// The mode shared by every zone, or `Auto` when they differ.
+    fn get_current_mode(&self) -> Mode {
+        let mut modes = self
+            .zones
+            .iter()
+            .map(|zone| zone.controller.get_current_mode());
+        match modes.next() {
+            Some(first) if modes.all(|mode| mode == first) => first,
+            _ => Mode::default(),
+        }
+    }
+
+    /// The first zone's config with the shortest control and sample periods of
+    /// any zone, so the loop runs often enough for all of them.
+    fn get_config(&self) -> Option<Config> {
+        self.zones
+            .iter()
+            .filter_map(|zone| zone.controller.get_config())
+            .reduce(|fastest, config| Config {
+                control_period: fastest.control_period.min(config.control_period),
+                sample_period: fastest.sample_period.min(config.sample_period),
+                ..fastest
+            })
+    }
+
+    /// Zones ramp independently, so there is no single effective setpoint.
+    fn get_effective_setpoint(&self) -> Option<f32> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_reader::ConfigError;
+    use crate::temperature_controller::MockHandleTemperature;
+
+    fn zone_controller(state: SystemState, fails: bool) -> Box<MockHandleTemperature> {
+        let mut controller = Box::new(MockHandleTemperature::new());
+        controller
+            .expect_update_temperature()
+            .times(1)
+            .returning(move || match fails {
+                true => Err(ControllerError::Config(ConfigError::Missing(None))),
+                false => Ok(()),
+            });
+        controller
+            .expect_get_current_state()
+            .returning(move || state);
+        controller
+            .expect_get_current_mode()
+            .returning(Mode::default);
+        controller
+            .expect_get_config()
+            .returning(|| Some(Config::default()));
+        controller
+            .expect_get_effective_setpoint()
+            .returning(|| Some(21f32));
+        controller
+    }
+
+    #[test]
+    fn failing_zone_does_not_stop_others() {
+        let mut zone_manager = ZoneManager::default();
+        zone_manager.add_zone(
+            "kitchen".to_string(),
+            zone_controller(SystemState::Idle, true),
+        );
+        zone_manager.add_zone(
+            "bedroom".to_string(),
+            zone_controller(SystemState::Heating, false),
+        );
+
+        let updated = zone_manager.update_temperature();
+        assert!(matches!(
+            updated,
+            Err(ControllerError::Zones(ref errors))
+                if matches!(&errors[..], [ControllerError::Zone { zone, .. }] if zone == "kitchen")
+        ));
+
+        let kitchen = zone_manager.get_zone_status("kitchen").unwrap();
+        assert!(kitchen.last_error.is_some());
+        assert!(kitchen.consecutive_failures == 1);
+        let bedroom = zone_manager.get_zone_status("bedroom").unwrap();
+        assert!(bedroom.last_error.is_none());
+        assert!(bedroom.state == SystemState::Heating);
+        assert!(bedroom.effective_setpoint == Some(21f32));
+    }
+
+    #[test]
+    fn every_failing_zone_is_reported() {
+        let mut zone_manager = ZoneManager::default();
+        for (name, fails) in [("kitchen", true), ("bedroom", false), ("hall", true)] {
+            zone_manager.add_zone(name.to_string(), zone_controller(SystemState::Idle, fails));
+        }
+
+        let updated = zone_manager.update_temperature();
+        let failed_zones = match &updated {
+            Err(ControllerError::Zones(errors)) => errors
+                .iter()
+                .filter_map(|error| match error {
+                    ControllerError::Zone { zone, .. } => Some(zone.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+            _ => Vec::new(),
+        };
+        assert!(failed_zones == vec!["kitchen", "hall"]);
+        assert!(updated.unwrap_err().to_string() == "2 zones failed: kitchen, hall");
+    }
+
+    #[test]
+    fn aggregate_status_counts_zones() {
+        let mut zone_manager = ZoneManager::default();
+        zone_manager.add_zone(
+            "kitchen".to_string(),
+            zone_controller(SystemState::Cooling, false),
+        );
+        zone_manager.add_zone(
+            "bedroom".to_string(),
+            zone_controller(SystemState::Heating, true),
+        );
+        zone_manager.add_zone(
+            "hall".to_string(),
+            zone_controller(SystemState::Idle, false),
+        );
+
+        assert!(zone_manager.update_temperature().is_err());
+
+        let aggregate = zone_manager.get_aggregate_status();
+        assert!(
+            aggregate
+                == AggregateStatus {
+                    zones: 3,
+                    heating: 1,
+                    cooling: 1,
+                    failing: 1,
+                }
+        );
+        assert!(zone_manager.get_current_state() == SystemState::Heating);
+    }
+
+    #[test]
+    fn config_uses_fastest_periods() {
+        let mut zone_manager = ZoneManager::default();
+        for (name, control, sample) in [("kitchen", 10, 2), ("bedroom", 5, 5)] {
+            let mut controller = Box::new(MockHandleTemperature::new());
+            controller.expect_get_config().returning(move || {
+                Some(Config {
+                    control_period: std::time::Duration::from_secs(control),
+                    sample_period: std::time::Duration::from_secs(sample),
+                    ..Config::default()
+                })
+            });
+            zone_manager.add_zone(name.to_string(), controller);
+        }
+
+        let config = zone_manager.get_config();
+        assert!(config.is_some());
+        let config = config.unwrap();
+        assert!(config.control_period == std::time::Duration::from_secs(5));
+        assert!(config.sample_period == std::time::Duration::from_secs(2));
+    }
+}